@@ -0,0 +1,199 @@
+use bevy::prelude::*;
+use rand::Rng;
+use crate::components::*;
+use crate::resources::*;
+use crate::config::GameConfig;
+
+// Where the puck will be when its center reaches target_x, following the same
+// top/bottom wall reflections as move_puck. Returns the position and velocity at
+// that point, or None if the puck is moving away from target_x. Bounces beyond
+// max_bounces are not seen: the path is simply clamped to the rink there.
+pub fn predict_intercept(
+    position: Vec2,
+    velocity: Vec2,
+    target_x: f32,
+    max_bounces: u32,
+    config: &GameConfig,
+) -> Option<(Vec2, Vec2)> {
+    if velocity.x == 0.0 {
+        return None;
+    }
+    let time_to_reach = (target_x - position.x) / velocity.x;
+    if time_to_reach < 0.0 {
+        return None;
+    }
+
+    let boundary = config.rink_height / 2.0 - config.puck_size / 2.0;
+    let span = 2.0 * boundary;
+
+    // Unfold the walls into a straight line, then fold the result back into the rink
+    let unfolded_y = position.y + velocity.y * time_to_reach + boundary;
+    let bounces = (unfolded_y / span).floor().abs() as u32;
+    if bounces > max_bounces {
+        let y = (unfolded_y - boundary).clamp(-boundary, boundary);
+        return Some((Vec2::new(target_x, y), velocity));
+    }
+
+    let folded = unfolded_y.rem_euclid(2.0 * span);
+    let (y, velocity_y) = if folded <= span {
+        (folded - boundary, velocity.y)
+    } else {
+        (2.0 * span - folded - boundary, -velocity.y)
+    };
+    Some((Vec2::new(target_x, y), Vec2::new(velocity.x, velocity_y)))
+}
+
+// The collision normal (paddle center to puck center) that sends a puck arriving
+// with incoming_velocity off in aim_direction, treating the hit as a perfect
+// reflection off a still paddle. None if that shot would need the puck to pass
+// through the paddle.
+pub fn aim_normal(incoming_velocity: Vec2, aim_direction: Vec2, forward: f32) -> Option<Vec2> {
+    let outgoing = aim_direction.normalize_or_zero() * incoming_velocity.length();
+    let normal = (outgoing - incoming_velocity).normalize_or_zero();
+    if normal.x * forward <= 0.0 {
+        return None;
+    }
+    Some(normal)
+}
+
+// Decides where the CPU wants its paddle and pushes it there through PaddleInput,
+// so it moves under the same rules as a player
+pub fn drive_cpu_paddles(
+    mut paddle_query: Query<(&Position, &mut PaddleInput, &mut CpuPaddle, &Paddle), Without<Puck>>,
+    all_paddles: Query<(&Position, &Paddle)>,
+    puck_query: Query<(&Position, &Velocity), With<Puck>>,
+    settings: Res<MatchSettings>,
+    config: Res<GameConfig>,
+    mut game_rng: ResMut<GameRng>,
+    time: Res<Time>,
+) {
+    let Ok((puck_position, puck_velocity)) = puck_query.single() else {
+        return;
+    };
+    let difficulty = settings.difficulty;
+    let collision_distance = config.paddle_radius + config.puck_size / 2.0;
+
+    // Where every paddle is, the player's too, so the CPU can aim away from its opponent
+    let paddle_positions: Vec<(Side, Vec2)> = all_paddles
+        .iter()
+        .map(|(position, paddle)| (paddle.side, position.0))
+        .collect();
+
+    for (position, mut input, mut cpu, paddle) in &mut paddle_query {
+        // Only re-read the puck every reaction_delay seconds
+        if cpu.reaction_timer.tick(time.delta()).just_finished() {
+            // The CPU defends from its starting line and faces the opponent's end
+            let home_x = config.paddle_start(paddle.side).x;
+            let forward = match paddle.side {
+                Side::Left => 1.0,
+                Side::Right => -1.0,
+            };
+            let puck_position = puck_position.0;
+            let in_own_half = puck_position.x * forward <= 0.0;
+
+            cpu.target = if in_own_half && puck_velocity.0.length() < config.puck_speed * 0.25 {
+                // A slow or resting puck has to be gone after: get behind it, then drive through it
+                let behind = puck_position - Vec2::X * forward * collision_distance;
+                if (position.0.x - behind.x) * forward <= config.paddle_radius {
+                    puck_position + Vec2::X * forward * config.paddle_radius
+                } else {
+                    behind
+                }
+            } else {
+                // Meet the puck slightly in front of the paddle line
+                let contact_x = home_x + forward * collision_distance * 0.8;
+                let intercept = predict_intercept(
+                    puck_position,
+                    puck_velocity.0,
+                    contact_x,
+                    difficulty.bounce_lookahead(),
+                    &config,
+                );
+
+                let target_y = match intercept {
+                    Some((contact, incoming_velocity)) => {
+                        let mut target_y = contact.y;
+
+                        if difficulty.aims_shots() {
+                            // Aim for the corner of the far end that the opponent is not covering
+                            let opponent_y = paddle_positions
+                                .iter()
+                                .find(|(side, _)| *side != paddle.side)
+                                .map(|(_, position)| position.y)
+                                .unwrap_or(0.0);
+                            let corner_y = (config.rink_height / 2.0 - config.puck_size) * -opponent_y.signum();
+                            let aim_point = Vec2::new(forward * config.rink_width / 2.0, corner_y);
+
+                            if let Some(normal) = aim_normal(incoming_velocity, aim_point - contact, forward) {
+                                // Never offset so far that the paddle would miss the puck
+                                let offset = (normal.y * collision_distance)
+                                    .clamp(-collision_distance * 0.7, collision_distance * 0.7);
+                                target_y -= offset;
+                            }
+                        }
+
+                        let error = difficulty.prediction_error();
+                        target_y + game_rng.rng.gen_range(-error..=error)
+                    }
+                    // Drift back toward the middle while the puck is going away
+                    None => 0.0,
+                };
+                Vec2::new(home_x, target_y)
+            };
+        }
+
+        // Full input covers paddle_speed * delta, the CPU is capped at its difficulty's speed
+        let full_step = config.paddle_speed * time.delta_secs();
+        if full_step > 0.0 {
+            input.0 = ((cpu.target - position.0) / full_step).clamp_length_max(difficulty.speed_factor());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::ecs::system::RunSystemOnce;
+    use super::*;
+
+    // Where a Hard CPU on the right wants to meet a puck coming straight at it,
+    // with its opponent standing at opponent_y
+    fn cpu_target(opponent_y: f32) -> Vec2 {
+        let config = GameConfig::default();
+        let mut world = World::new();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(0.1));
+        world.insert_resource(time);
+        world.insert_resource(MatchSettings { difficulty: Difficulty::Hard, ..default() });
+        world.insert_resource(config.clone());
+        world.insert_resource(GameRng::new(1));
+
+        world.spawn((Position(Vec2::new(-300.0, opponent_y)), PaddleInput::default(), Paddle { side: Side::Left }));
+        let cpu = world
+            .spawn((
+                Position(config.paddle_start(Side::Right)),
+                PaddleInput::default(),
+                CpuPaddle {
+                    reaction_timer: Timer::from_seconds(0.05, TimerMode::Repeating),
+                    target: Vec2::ZERO,
+                },
+                Paddle { side: Side::Right },
+            ))
+            .id();
+        world.spawn((Position(Vec2::ZERO), Velocity(Vec2::new(config.puck_speed, 0.0)), Puck));
+
+        world.run_system_once(drive_cpu_paddles).unwrap();
+        world.get::<CpuPaddle>(cpu).unwrap().target
+    }
+
+    // Meeting the puck above its path sends it off low, and below sends it high
+    #[test]
+    fn cpu_aims_away_from_its_opponent() {
+        let opponent_high = cpu_target(200.0);
+        let opponent_low = cpu_target(-200.0);
+        assert!(
+            opponent_high.y > opponent_low.y,
+            "aimed the same way with the opponent high ({opponent_high}) and low ({opponent_low})"
+        );
+    }
+}
@@ -0,0 +1,502 @@
+use std::fs;
+use std::path::Path;
+use bevy::input::keyboard::NativeKeyCode;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::components::*;
+use crate::resources::*;
+use crate::systems::spawn_option_button;
+
+// Keyboard controls, which players can change on the controls screen. They're
+// kept in CONTROLS_FILE between runs.
+
+pub const CONTROLS_FILE: &str = "controls.ron";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    LeftUp,
+    LeftDown,
+    LeftLeft,
+    LeftRight,
+    RightUp,
+    RightDown,
+    RightLeft,
+    RightRight,
+    Pause,
+}
+
+impl Action {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::LeftUp => "RED UP",
+            Action::LeftDown => "RED DOWN",
+            Action::LeftLeft => "RED LEFT",
+            Action::LeftRight => "RED RIGHT",
+            Action::RightUp => "BLUE UP",
+            Action::RightDown => "BLUE DOWN",
+            Action::RightLeft => "BLUE LEFT",
+            Action::RightRight => "BLUE RIGHT",
+            Action::Pause => "PAUSE",
+        }
+    }
+
+    // Up, down, left and right for a side
+    pub fn movement(side: Side) -> [Action; 4] {
+        match side {
+            Side::Left => [Action::LeftUp, Action::LeftDown, Action::LeftLeft, Action::LeftRight],
+            Side::Right => [Action::RightUp, Action::RightDown, Action::RightLeft, Action::RightRight],
+        }
+    }
+}
+
+// Keys that can be bound, with the names shown for them. Backspace is left out,
+// it cancels rebinding.
+const KEYS: [(KeyCode, &str); 66] = [
+    (KeyCode::KeyA, "A"), (KeyCode::KeyB, "B"), (KeyCode::KeyC, "C"), (KeyCode::KeyD, "D"),
+    (KeyCode::KeyE, "E"), (KeyCode::KeyF, "F"), (KeyCode::KeyG, "G"), (KeyCode::KeyH, "H"),
+    (KeyCode::KeyI, "I"), (KeyCode::KeyJ, "J"), (KeyCode::KeyK, "K"), (KeyCode::KeyL, "L"),
+    (KeyCode::KeyM, "M"), (KeyCode::KeyN, "N"), (KeyCode::KeyO, "O"), (KeyCode::KeyP, "P"),
+    (KeyCode::KeyQ, "Q"), (KeyCode::KeyR, "R"), (KeyCode::KeyS, "S"), (KeyCode::KeyT, "T"),
+    (KeyCode::KeyU, "U"), (KeyCode::KeyV, "V"), (KeyCode::KeyW, "W"), (KeyCode::KeyX, "X"),
+    (KeyCode::KeyY, "Y"), (KeyCode::KeyZ, "Z"),
+    (KeyCode::Digit0, "0"), (KeyCode::Digit1, "1"), (KeyCode::Digit2, "2"), (KeyCode::Digit3, "3"),
+    (KeyCode::Digit4, "4"), (KeyCode::Digit5, "5"), (KeyCode::Digit6, "6"), (KeyCode::Digit7, "7"),
+    (KeyCode::Digit8, "8"), (KeyCode::Digit9, "9"),
+    (KeyCode::Numpad0, "NUM 0"), (KeyCode::Numpad1, "NUM 1"), (KeyCode::Numpad2, "NUM 2"),
+    (KeyCode::Numpad3, "NUM 3"), (KeyCode::Numpad4, "NUM 4"), (KeyCode::Numpad5, "NUM 5"),
+    (KeyCode::Numpad6, "NUM 6"), (KeyCode::Numpad7, "NUM 7"), (KeyCode::Numpad8, "NUM 8"),
+    (KeyCode::Numpad9, "NUM 9"),
+    (KeyCode::ArrowUp, "UP"), (KeyCode::ArrowDown, "DOWN"), (KeyCode::ArrowLeft, "LEFT"),
+    (KeyCode::ArrowRight, "RIGHT"),
+    (KeyCode::Escape, "ESC"), (KeyCode::Space, "SPACE"), (KeyCode::Enter, "ENTER"), (KeyCode::Tab, "TAB"),
+    (KeyCode::ShiftLeft, "LEFT SHIFT"), (KeyCode::ShiftRight, "RIGHT SHIFT"),
+    (KeyCode::ControlLeft, "LEFT CTRL"), (KeyCode::ControlRight, "RIGHT CTRL"),
+    (KeyCode::AltLeft, "LEFT ALT"), (KeyCode::AltRight, "RIGHT ALT"),
+    (KeyCode::Comma, ","), (KeyCode::Period, "."), (KeyCode::Slash, "/"), (KeyCode::Semicolon, ";"),
+    (KeyCode::BracketLeft, "["), (KeyCode::BracketRight, "]"),
+];
+
+// Space and Enter skip the goal replay and press menu buttons, so no action can have them
+const RESERVED_KEYS: [KeyCode; 2] = [KeyCode::Space, KeyCode::Enter];
+
+pub fn key_label(key: KeyCode) -> &'static str {
+    KEYS.iter().find(|(known, _)| *known == key).map_or("?", |(_, label)| label)
+}
+
+// Saved by KeyCode name, like KeyW
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.iter().map(|(key, _)| *key).find(|key| format!("{key:?}") == name)
+}
+
+// Why a key can't be bound to an action
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RebindError {
+    Taken(Action), // Already the key for this other action
+    Reserved,
+}
+
+impl RebindError {
+    pub fn describe(&self, key: KeyCode) -> String {
+        match self {
+            RebindError::Taken(other) => format!("{} is already {}", key_label(key), other.label()),
+            RebindError::Reserved => format!("{} is kept for the menus", key_label(key)),
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct KeyBindings {
+    keys: Vec<(Action, KeyCode)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            keys: vec![
+                (Action::LeftUp, KeyCode::KeyW),
+                (Action::LeftDown, KeyCode::KeyS),
+                (Action::LeftLeft, KeyCode::KeyA),
+                (Action::LeftRight, KeyCode::KeyD),
+                (Action::RightUp, KeyCode::ArrowUp),
+                (Action::RightDown, KeyCode::ArrowDown),
+                (Action::RightLeft, KeyCode::ArrowLeft),
+                (Action::RightRight, KeyCode::ArrowRight),
+                (Action::Pause, KeyCode::Escape),
+            ],
+        }
+    }
+}
+
+impl KeyBindings {
+    // Unidentified for an action left without a key, which never reads as pressed
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.keys
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .map_or(KeyCode::Unidentified(NativeKeyCode::Unidentified), |(_, key)| *key)
+    }
+
+    pub fn pressed(&self, keyboard_input: &ButtonInput<KeyCode>, action: Action) -> bool {
+        keyboard_input.pressed(self.key(action))
+    }
+
+    pub fn just_pressed(&self, keyboard_input: &ButtonInput<KeyCode>, action: Action) -> bool {
+        keyboard_input.just_pressed(self.key(action))
+    }
+
+    // Fails if another action already uses the key, or the menus need it
+    pub fn rebind(&mut self, action: Action, key: KeyCode) -> Result<(), RebindError> {
+        if RESERVED_KEYS.contains(&key) {
+            return Err(RebindError::Reserved);
+        }
+        if let Some((other, _)) = self.keys.iter().find(|(other, bound)| *bound == key && *other != action) {
+            return Err(RebindError::Taken(*other));
+        }
+        match self.keys.iter_mut().find(|(bound, _)| *bound == action) {
+            Some(binding) => binding.1 = key,
+            None => self.keys.push((action, key)),
+        }
+        Ok(())
+    }
+
+    // As W/A/S/D: up, left, down then right
+    pub fn movement_label(&self, side: Side) -> String {
+        let [up, down, left, right] = Action::movement(side).map(|action| key_label(self.key(action)));
+        format!("{up}/{left}/{down}/{right}")
+    }
+
+    pub fn help_text(&self) -> String {
+        format!(
+            "Left Player: {}\nRight Player: {}\nControllers: Stick or D-Pad\n{}/START: Pause\nSPACE/ENTER/A/B: Skip Goal Replay",
+            self.movement_label(Side::Left),
+            self.movement_label(Side::Right),
+            key_label(self.key(Action::Pause)),
+        )
+    }
+
+    // Anything missing from the file gets its default key. A file that leaves an
+    // action with no key at all is refused.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+        let saved: Vec<(Action, String)> =
+            ron::from_str(&text).map_err(|e| format!("{} is not a controls file: {e}", path.display()))?;
+        let mut bindings = Self { keys: Vec::new() };
+        for (action, name) in saved {
+            match key_from_name(&name) {
+                Some(key) => {
+                    if let Err(error) = bindings.rebind(action, key) {
+                        warn!("{}, ignoring it for {}", error.describe(key), action.label());
+                    }
+                }
+                None => warn!("Unknown key {name} for {} in {}", action.label(), path.display()),
+            }
+        }
+        for (action, key) in Self::default().keys {
+            let missing = bindings.keys.iter().all(|(bound, _)| *bound != action);
+            if missing && let Err(error) = bindings.rebind(action, key) {
+                return Err(format!("{} has no key in {}: {}", action.label(), path.display(), error.describe(key)));
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let saved: Vec<(Action, String)> = self.keys.iter().map(|(action, key)| (*action, format!("{key:?}"))).collect();
+        let text = ron::ser::to_string_pretty(&saved, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("can't encode controls: {e}"))?;
+        fs::write(path, text).map_err(|e| format!("can't write {}: {e}", path.display()))
+    }
+
+    // The saved controls, or the defaults the first time
+    pub fn load_or_default() -> Self {
+        Self::load_or_default_from(Path::new(CONTROLS_FILE))
+    }
+
+    fn load_or_default_from(path: &Path) -> Self {
+        if !path.exists() {
+            return Self::default();
+        }
+        Self::load(path).unwrap_or_else(|error| {
+            warn!("Couldn't load controls, using the defaults: {error}");
+            Self::default()
+        })
+    }
+}
+
+// The action waiting on a key press
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<Action>);
+
+#[allow(clippy::type_complexity)]
+pub fn handle_controls_button(
+    mut query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<ControlsButton>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, mut color) in &mut query {
+        match *interaction {
+            Interaction::Pressed => next_state.set(GameState::Controls),
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+}
+
+// Controls Systems
+pub fn setup_controls_screen(mut commands: Commands, bindings: Res<KeyBindings>) {
+    commands.insert_resource(Rebinding::default());
+
+    let heading = |parent: &mut ChildSpawnerCommands, label: &str, color: Color| {
+        parent.spawn((
+            Text::new(label),
+            TextFont {
+                font_size: 24.0,
+                ..default()
+            },
+            TextColor(color),
+            TextLayout::new_with_justify(Justify::Center),
+            Node {
+                width: Val::Px(170.0),
+                ..default()
+            },
+        ));
+    };
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(8.0),
+            ..default()
+        },
+        ControlsUI,
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::new("CONTROLS"),
+            TextFont {
+                font_size: 60.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            Node {
+                margin: UiRect::bottom(Val::Px(10.0)),
+                ..default()
+            },
+        ));
+
+        // A column for each side, with a row for each direction
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            ..default()
+        }).with_children(|parent| {
+            heading(parent, "", Color::BLACK);
+            heading(parent, "RED", Color::srgb(0.8, 0.2, 0.2));
+            heading(parent, "BLUE", Color::srgb(0.2, 0.2, 0.8));
+        });
+        let left = Action::movement(Side::Left);
+        let right = Action::movement(Side::Right);
+        for (index, direction) in ["UP", "DOWN", "LEFT", "RIGHT"].into_iter().enumerate() {
+            parent.spawn(Node {
+                flex_direction: FlexDirection::Row,
+                column_gap: Val::Px(10.0),
+                ..default()
+            }).with_children(|parent| {
+                heading(parent, direction, Color::BLACK);
+                for action in [left[index], right[index]] {
+                    spawn_option_button(parent, key_label(bindings.key(action)), Color::srgb(0.35, 0.35, 0.35), BindingButton(action));
+                }
+            });
+        }
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            ..default()
+        }).with_children(|parent| {
+            heading(parent, "PAUSE", Color::BLACK);
+            spawn_option_button(
+                parent,
+                key_label(bindings.key(Action::Pause)),
+                Color::srgb(0.35, 0.35, 0.35),
+                BindingButton(Action::Pause),
+            );
+            heading(parent, "", Color::BLACK);
+        });
+
+        parent.spawn((
+            Text::new("Click a key to change it"),
+            TextFont {
+                font_size: 22.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            TextLayout::new_with_justify(Justify::Center),
+            Node {
+                margin: UiRect::vertical(Val::Px(10.0)),
+                ..default()
+            },
+            ControlsStatusText,
+        ));
+
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            ..default()
+        }).with_children(|parent| {
+            spawn_option_button(parent, "CONTROLLERS", Color::srgb(0.35, 0.35, 0.35), ControllersButton);
+            spawn_option_button(parent, "RESET", Color::srgb(0.35, 0.35, 0.35), ResetControlsButton);
+            spawn_option_button(parent, "BACK", Color::srgb(0.8, 0.2, 0.2), ControlsBackButton);
+        });
+    });
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn handle_controls_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut binding_query: Query<(&Interaction, &mut BackgroundColor, &BindingButton), Changed<Interaction>>,
+    mut reset_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ResetControlsButton>, Without<BindingButton>),
+    >,
+    back_query: Query<&Interaction, (Changed<Interaction>, With<ControlsBackButton>)>,
+    mut bindings: ResMut<KeyBindings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut status_query: Query<&mut Text, With<ControlsStatusText>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let mut status = None;
+
+    if let Some(action) = rebinding.0 {
+        // Whatever key comes first, apart from Backspace which gives up
+        if keyboard_input.just_pressed(KeyCode::Backspace) {
+            rebinding.0 = None;
+            status = Some("Click a key to change it".to_string());
+        } else if let Some(key) = keyboard_input.get_just_pressed().copied().find(|key| key_label(*key) != "?") {
+            rebinding.0 = None;
+            status = Some(match bindings.rebind(action, key) {
+                Ok(()) => {
+                    if let Err(error) = bindings.save(Path::new(CONTROLS_FILE)) {
+                        error!("Couldn't save controls: {error}");
+                    }
+                    format!("{} is now {}", action.label(), key_label(key))
+                }
+                Err(error) => format!("{}, pick another key", error.describe(key)),
+            });
+        }
+    } else if bindings.just_pressed(&keyboard_input, Action::Pause)
+        || back_query.iter().any(|interaction| *interaction == Interaction::Pressed)
+    {
+        next_state.set(GameState::Settings);
+        return;
+    }
+
+    for (interaction, mut color, button) in &mut binding_query {
+        match *interaction {
+            Interaction::Pressed => {
+                rebinding.0 = Some(button.0);
+                status = Some(format!("Press a key for {}\nBACKSPACE: Cancel", button.0.label()));
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+
+    for (interaction, mut color) in &mut reset_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *bindings = KeyBindings::default();
+                rebinding.0 = None;
+                if let Err(error) = bindings.save(Path::new(CONTROLS_FILE)) {
+                    error!("Couldn't save controls: {error}");
+                }
+                status = Some("Back to the default keys".to_string());
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+
+    if let Some(status) = status {
+        for mut text in &mut status_query {
+            *text = Text::new(status.clone());
+        }
+    }
+}
+
+// Relabels the key buttons, showing which one is waiting for a key
+pub fn update_controls_display(
+    bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+    button_query: Query<(&BindingButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (button, children) in &button_query {
+        let label = if rebinding.0 == Some(button.0) { "..." } else { key_label(bindings.key(button.0)) };
+        let mut texts = text_query.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            *text = Text::new(label);
+        }
+    }
+}
+
+pub fn cleanup_controls_screen(mut commands: Commands, query: Query<Entity, With<ControlsUI>>) {
+    for entity in &query {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<Rebinding>();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+
+    fn controls_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("air_hockey_{name}_{}.ron", std::process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn rebind_rejects_a_key_in_use() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(bindings.rebind(Action::RightUp, KeyCode::KeyW), Err(RebindError::Taken(Action::LeftUp)));
+        assert_eq!(bindings.key(Action::RightUp), KeyCode::ArrowUp);
+
+        // Giving an action the key it already has is fine
+        assert_eq!(bindings.rebind(Action::LeftUp, KeyCode::KeyW), Ok(()));
+    }
+
+    #[test]
+    fn rebind_rejects_menu_keys() {
+        let mut bindings = KeyBindings::default();
+        for key in [KeyCode::Space, KeyCode::Enter] {
+            assert_eq!(bindings.rebind(Action::Pause, key), Err(RebindError::Reserved));
+        }
+        assert_eq!(bindings.key(Action::Pause), KeyCode::Escape);
+    }
+
+    // Pause saved as W leaves LeftUp with nothing, since W was its only default
+    #[test]
+    fn conflicting_file_falls_back_to_the_defaults() {
+        let path = controls_file("conflict", r#"[(Pause, "KeyW")]"#);
+        assert!(KeyBindings::load(&path).is_err());
+        assert_eq!(KeyBindings::load_or_default_from(&path), KeyBindings::default());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_actions_get_their_default_keys() {
+        let path = controls_file("partial", r#"[(Pause, "KeyP")]"#);
+        let bindings = KeyBindings::load(&path).unwrap();
+        assert_eq!(bindings.key(Action::Pause), KeyCode::KeyP);
+        assert_eq!(bindings.key(Action::LeftUp), KeyCode::KeyW);
+        fs::remove_file(&path).unwrap();
+    }
+}
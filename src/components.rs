@@ -1,4 +1,7 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::bindings::Action;
+use crate::resources::{Difficulty, Rule};
 
 #[derive(Component)]
 pub struct Paddle {
@@ -8,20 +11,66 @@ pub struct Paddle {
 #[derive(Component)]
 pub struct Puck;
 
+// The rink background, sized to the window
+#[derive(Component)]
+pub struct Rink;
+
+// The goal at one end, -1 for the left end and 1 for the right
+#[derive(Component)]
+pub struct Goal(pub f32);
+
+// A goal post, as which end and which side of the goal mouth it's on
+#[derive(Component)]
+pub struct GoalPost(pub Vec2);
+
+// A black bar covering the world beyond one edge of the rink, as the way out from the middle
+#[derive(Component)]
+pub struct Letterbox(pub Vec2);
+
 #[derive(Component)]
 pub struct Velocity(pub Vec2);
 
+// Where the simulation has the puck or a paddle. Transform is only what gets
+// drawn, interpolated between PreviousPosition and this.
+#[derive(Component)]
+pub struct Position(pub Vec2);
+
+#[derive(Component)]
+pub struct PreviousPosition(pub Vec2);
+
+// Direction a paddle is being pushed this tick, up to length 1 for full speed
+#[derive(Component, Default)]
+pub struct PaddleInput(pub Vec2);
+
 #[derive(Component)]
 pub struct ScoreText;
 
+#[derive(Component)]
+pub struct ClockText;
+
 #[derive(Component)]
 pub struct PauseText;
 
+#[derive(Component)]
+pub struct ReplayText;
+
 #[derive(Component)]
 pub struct StartScreenUI;
 
 #[derive(Component)]
-pub struct PlayButton;
+pub struct OnePlayerButton;
+
+#[derive(Component)]
+pub struct TwoPlayerButton;
+
+#[derive(Component)]
+pub struct WatchReplayButton;
+
+#[derive(Component)]
+pub struct DifficultyButton(pub Difficulty);
+
+#[derive(Component)]
+pub struct RuleButton(pub Rule);
 
 #[derive(Component)]
 pub struct CountdownText;
@@ -38,8 +87,153 @@ pub struct RestartButton;
 #[derive(Component)]
 pub struct ResumeButton;
 
-#[derive(PartialEq)]
+#[derive(Component)]
+pub struct GameOverUI;
+
+#[derive(Component)]
+pub struct RematchButton;
+
+#[derive(Component)]
+pub struct MainMenuButton;
+
+// Drives a paddle instead of the keyboard
+#[derive(Component)]
+pub struct CpuPaddle {
+    pub reaction_timer: Timer,
+    pub target: Vec2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Side {
     Left,
     Right,
-}
\ No newline at end of file
+}
+
+#[derive(Component)]
+pub struct ReplayViewerUI;
+
+// The clickable bar along the bottom of the replay viewer
+#[derive(Component)]
+pub struct ReplayTimeline;
+
+#[derive(Component)]
+pub struct ReplayPlayhead;
+
+#[derive(Component)]
+pub struct ReplayStatusText;
+
+// Jumps the replay viewer to just before a goal
+#[derive(Component)]
+pub struct GoalMarker {
+    pub tick: u64,
+}
+
+#[derive(Component)]
+pub struct GoalReplayText;
+
+// A paddle that follows the mouse cursor
+#[derive(Component)]
+pub struct MousePaddle;
+
+#[derive(Component)]
+pub struct MouseControlButton;
+
+// A paddle moved by the other player in a networked match
+#[derive(Component)]
+pub struct RemotePaddle;
+
+#[derive(Component)]
+pub struct HostGameButton;
+
+#[derive(Component)]
+pub struct JoinGameButton;
+
+#[derive(Component)]
+pub struct SpectateButton;
+
+#[derive(Component)]
+pub struct LobbyButton;
+
+#[derive(Component)]
+pub struct ControllersButton;
+
+#[derive(Component)]
+pub struct ControlsButton;
+
+#[derive(Component)]
+pub struct SettingsButton;
+
+#[derive(Component)]
+pub struct SettingsUI;
+
+#[derive(Component)]
+pub struct WindowModeButton;
+
+#[derive(Component)]
+pub struct ResolutionButton;
+
+#[derive(Component)]
+pub struct SettingsBackButton;
+
+#[derive(Component)]
+pub struct ControlsUI;
+
+// Waits for a new key for this action when clicked
+#[derive(Component)]
+pub struct BindingButton(pub Action);
+
+#[derive(Component)]
+pub struct ResetControlsButton;
+
+#[derive(Component)]
+pub struct ControlsBackButton;
+
+#[derive(Component)]
+pub struct ControlsStatusText;
+
+#[derive(Component)]
+pub struct ControllersUI;
+
+// Holds a row for every connected controller
+#[derive(Component)]
+pub struct ControllerList;
+
+#[derive(Component)]
+pub struct ControllersBackButton;
+
+#[derive(Component)]
+pub struct LobbyUI;
+
+#[derive(Component)]
+pub struct LobbyHostButton;
+
+#[derive(Component)]
+pub struct LobbyReadyButton;
+
+#[derive(Component)]
+pub struct LobbyBackButton;
+
+#[derive(Component)]
+pub struct LobbyStatusText;
+
+// Holds a button for every game found
+#[derive(Component)]
+pub struct LobbyGameList;
+
+// Joins the game with this id
+#[derive(Component)]
+pub struct LobbyGameButton(pub u64);
+
+// Switches networked matches between host-authoritative and rollback
+#[derive(Component)]
+pub struct NetcodeButton;
+
+#[derive(Component)]
+pub struct ConnectingUI;
+
+#[derive(Component)]
+pub struct ConnectionLostUI;
+
+// Ends a networked match and goes back to the start screen
+#[derive(Component)]
+pub struct LeaveMatchButton;
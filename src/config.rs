@@ -0,0 +1,280 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use crate::components::*;
+use crate::resources::PuckDelayTimer;
+
+// The sizes and speeds that decide how the game feels. They're read from
+// CONFIG_FILE at startup and again whenever it's saved, so they can be tuned
+// while playing. Anything left out of the file keeps its default.
+
+pub const CONFIG_FILE: &str = "config.ron";
+const POLL_SECONDS: f32 = 0.5; // How often the file is checked for changes
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    // The rink in world units. The camera scales it to fit whatever size the
+    // window is. Called the window size before the window could be resized.
+    #[serde(alias = "window_width")]
+    pub rink_width: f32,
+    #[serde(alias = "window_height")]
+    pub rink_height: f32,
+    pub paddle_speed: f32,
+    pub paddle_radius: f32,
+    pub paddle_start_inset: f32, // How far from its end wall each paddle starts
+    pub puck_size: f32,
+    pub puck_speed: f32,
+    pub puck_max_speed: f32,
+    pub puck_linear_drag: f32, // Fraction of speed lost per second in realistic physics
+    pub puck_rest_speed: f32, // Below this the puck stops dead
+    pub wall_restitution: f32,
+    pub paddle_restitution: f32, // Share of the puck's approach speed kept on a paddle hit
+    pub center_line_margin: f32, // Gap paddles must keep from the center line
+    pub goal_width: f32, // Opening in the middle of each end wall
+    pub goal_depth: f32,
+    pub goal_post_radius: f32,
+    pub serve_delay: f32, // Seconds before the puck moves at the start and after a goal
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            rink_width: 800.0,
+            rink_height: 600.0,
+            paddle_speed: 550.0,
+            paddle_radius: 40.0,
+            paddle_start_inset: 50.0,
+            puck_size: 65.0,
+            puck_speed: 600.0,
+            puck_max_speed: 1500.0,
+            puck_linear_drag: 0.3,
+            puck_rest_speed: 15.0,
+            wall_restitution: 0.85,
+            paddle_restitution: 0.9,
+            center_line_margin: 10.0,
+            goal_width: 200.0,
+            goal_depth: 12.0,
+            goal_post_radius: 6.0,
+            serve_delay: 2.0,
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn paddle_start(&self, side: Side) -> Vec2 {
+        let x = self.rink_width / 2.0 - self.paddle_start_inset;
+        match side {
+            Side::Left => Vec2::new(-x, 0.0),
+            Side::Right => Vec2::new(x, 0.0),
+        }
+    }
+
+    // The first field that doesn't make sense, named as it is in the file
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("rink_width", self.rink_width),
+            ("rink_height", self.rink_height),
+            ("paddle_speed", self.paddle_speed),
+            ("paddle_radius", self.paddle_radius),
+            ("puck_size", self.puck_size),
+            ("puck_speed", self.puck_speed),
+            ("puck_max_speed", self.puck_max_speed),
+            ("goal_width", self.goal_width),
+            ("goal_depth", self.goal_depth),
+            ("goal_post_radius", self.goal_post_radius),
+        ];
+        for (field, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{field} must be more than 0, got {value}"));
+            }
+        }
+        let not_negative = [
+            ("puck_linear_drag", self.puck_linear_drag),
+            ("puck_rest_speed", self.puck_rest_speed),
+            ("center_line_margin", self.center_line_margin),
+            ("serve_delay", self.serve_delay),
+        ];
+        for (field, value) in not_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{field} can't be negative, got {value}"));
+            }
+        }
+        for (field, value) in [("wall_restitution", self.wall_restitution), ("paddle_restitution", self.paddle_restitution)] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("{field} must be from 0 to 1, got {value}"));
+            }
+        }
+
+        if self.puck_max_speed < self.puck_speed {
+            return Err(format!(
+                "puck_max_speed must be at least puck_speed ({}), got {}",
+                self.puck_speed, self.puck_max_speed
+            ));
+        }
+        if self.puck_size >= self.rink_height {
+            return Err(format!("puck_size must be less than rink_height ({}), got {}", self.rink_height, self.puck_size));
+        }
+        if self.paddle_radius * 2.0 >= self.rink_height {
+            return Err(format!(
+                "paddle_radius must be less than half of rink_height ({}), got {}",
+                self.rink_height, self.paddle_radius
+            ));
+        }
+        if self.goal_width > self.rink_height {
+            return Err(format!("goal_width can't be more than rink_height ({}), got {}", self.rink_height, self.goal_width));
+        }
+        // Otherwise nobody could ever score
+        let mouth = self.goal_width - 2.0 * self.goal_post_radius;
+        if mouth <= self.puck_size {
+            return Err(format!(
+                "goal_width ({}) less the goal posts ({} each) must leave room for a puck_size of {}",
+                self.goal_width, self.goal_post_radius, self.puck_size
+            ));
+        }
+
+        // Each paddle needs room to start between its end wall and the center line
+        let nearest = self.paddle_radius;
+        let furthest = self.rink_width / 2.0 - self.center_line_margin - self.paddle_radius;
+        if nearest > furthest {
+            return Err(format!(
+                "paddle_radius ({}) and center_line_margin ({}) leave the paddles no room in a rink_width of {}",
+                self.paddle_radius, self.center_line_margin, self.rink_width
+            ));
+        }
+        if !(nearest..=furthest).contains(&self.paddle_start_inset) {
+            return Err(format!(
+                "paddle_start_inset must be from {nearest} to {furthest}, got {}",
+                self.paddle_start_inset
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+        let config: Self = ron::from_str(&text).map_err(|e| format!("{} is not a config file: {e}", path.display()))?;
+        config.validate().map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(config)
+    }
+
+    // The config file, or the defaults if there isn't one
+    pub fn load_or_default() -> Self {
+        let path = Path::new(CONFIG_FILE);
+        if !path.exists() {
+            return Self::default();
+        }
+        Self::load(path).unwrap_or_else(|error| {
+            warn!("Couldn't load the config, using the defaults: {error}");
+            Self::default()
+        })
+    }
+}
+
+// Watches the config file for changes
+#[derive(Resource)]
+pub struct ConfigFile {
+    pub loaded: GameConfig, // The last good config read, put back after networked matches and replays
+    modified: Option<SystemTime>,
+    poll_timer: Timer,
+}
+
+impl ConfigFile {
+    pub fn new(loaded: GameConfig) -> Self {
+        Self {
+            loaded,
+            modified: modified_time(Path::new(CONFIG_FILE)),
+            poll_timer: Timer::from_seconds(POLL_SECONDS, TimerMode::Repeating),
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// Not during networked matches, replays, or local matches while they're being
+// recorded, since a replay only saves the config its match started with.
+// Changes made meanwhile are picked up once they're over.
+pub fn reload_config(mut file: ResMut<ConfigFile>, mut config: ResMut<GameConfig>, time: Res<Time<Real>>) {
+    if !file.poll_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let path = Path::new(CONFIG_FILE);
+    let modified = modified_time(path);
+    if modified == file.modified {
+        return;
+    }
+    file.modified = modified;
+
+    // A bad edit keeps the game running as it was until the file is fixed
+    let loaded = match modified {
+        Some(_) => GameConfig::load(path),
+        None => Ok(GameConfig::default()),
+    };
+    match loaded {
+        Ok(loaded) => {
+            info!("Reloaded {CONFIG_FILE}");
+            file.loaded = loaded.clone();
+            config.set_if_neq(loaded);
+        }
+        Err(error) => warn!("Keeping the current config: {error}"),
+    }
+}
+
+// Resizes what's already in the rink to match a changed config. Speeds and the
+// rest are read every tick, so they take effect on their own. The meshes are
+// redrawn by resize_meshes.
+pub fn apply_config(
+    config: Res<GameConfig>,
+    mut puck_timer: ResMut<PuckDelayTimer>,
+    mut rink_query: Query<&mut Sprite, (With<Rink>, Without<Puck>)>,
+    mut puck_query: Query<&mut Sprite, With<Puck>>,
+    mut goal_query: Query<(&mut Transform, &Goal)>,
+    mut post_query: Query<(&mut Transform, &GoalPost), Without<Goal>>,
+) {
+    puck_timer.0.set_duration(Duration::from_secs_f32(config.serve_delay));
+
+    for mut sprite in &mut rink_query {
+        sprite.custom_size = Some(Vec2::new(config.rink_width, config.rink_height));
+    }
+    for mut sprite in &mut puck_query {
+        sprite.custom_size = Some(Vec2::splat(config.puck_size));
+    }
+    for (mut transform, goal) in &mut goal_query {
+        transform.translation.x = goal.0 * config.rink_width / 2.0;
+    }
+    for (mut transform, post) in &mut post_query {
+        transform.translation.x = post.0.x * config.rink_width / 2.0;
+        transform.translation.y = post.0.y * config.goal_width / 2.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        assert_eq!(GameConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn puck_must_fit_through_the_goal() {
+        let config = GameConfig { goal_width: 60.0, ..default() };
+        assert!(config.validate().is_err());
+        // Wide enough on its own, but not once the posts are in the way
+        let config = GameConfig { goal_width: 70.0, goal_post_radius: 6.0, ..default() };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn paddle_must_fit_in_its_half() {
+        for paddle_radius in [300.0, 200.0, 1000.0] {
+            let config = GameConfig { paddle_radius, ..default() };
+            assert!(config.validate().is_err(), "paddle_radius {paddle_radius} was accepted");
+        }
+    }
+}
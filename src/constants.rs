@@ -1,8 +1,11 @@
-pub const WINDOW_WIDTH: f32 = 800.0;
-pub const WINDOW_HEIGHT: f32 = 600.0;
-pub const PADDLE_SPEED: f32 = 550.0;
-pub const PADDLE_RADIUS: f32 = 40.0;
-pub const PUCK_SIZE: f32 = 65.0;
-pub const PUCK_SPEED: f32 = 600.0;
-pub const LEFT_PADDLE_X: f32 = -WINDOW_WIDTH / 2.0 + 50.0;
-pub const RIGHT_PADDLE_X: f32 = WINDOW_WIDTH / 2.0 - 50.0;
\ No newline at end of file
+// Sizes and speeds are in GameConfig, which is read from config.ron
+pub const DEFAULT_TICK_RATE: f64 = 60.0; // Simulation ticks per second
+pub const MAX_TICK_RATE: f64 = 1000.0;
+
+// Instant replay after a goal, played across the serve delay and slowing to a crawl at the goal
+pub const GOAL_REPLAY_SECONDS: f64 = 3.0;
+
+// Networked matches
+pub const DEFAULT_PORT: u16 = 7777;
+pub const DEFAULT_SPECTATOR_PORT: u16 = 7778;
+pub const DISCOVERY_PORT: u16 = 7779; // Hosts in the lobby answer players looking for games here
@@ -0,0 +1,384 @@
+use std::fs;
+use std::path::Path;
+use bevy::camera::ScalingMode;
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PrimaryWindow, WindowMode};
+use serde::{Deserialize, Serialize};
+use crate::bindings::{Action, KeyBindings};
+use crate::components::*;
+use crate::config::GameConfig;
+use crate::resources::*;
+use crate::systems::spawn_option_button;
+
+// How the game is shown, changed on the settings screen and kept in DISPLAY_FILE
+// between runs. The rink is always the same size in the world, and the camera
+// scales it to fit whatever the window is, with black bars where their shapes
+// don't match.
+
+pub const DISPLAY_FILE: &str = "display.ron";
+pub const RESOLUTIONS: [(u32, u32); 5] = [(800, 600), (1024, 768), (1280, 720), (1600, 900), (1920, 1080)];
+
+// Menus are laid out for a window this size and scaled from it
+const UI_REFERENCE_SIZE: Vec2 = Vec2::new(800.0, 600.0);
+// Far bigger than any rink, so the bars always reach the window edges
+const LETTERBOX_SIZE: f32 = 100_000.0;
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DisplaySettings {
+    pub fullscreen: bool,
+    pub resolution: (u32, u32), // Window size when not fullscreen
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            fullscreen: false,
+            resolution: RESOLUTIONS[0],
+        }
+    }
+}
+
+impl DisplaySettings {
+    pub fn mode(&self) -> WindowMode {
+        if self.fullscreen {
+            WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+        } else {
+            WindowMode::Windowed
+        }
+    }
+
+    pub fn cycle_resolution(&mut self) {
+        let index = RESOLUTIONS.iter().position(|&r| r == self.resolution);
+        self.resolution = RESOLUTIONS[index.map_or(0, |index| (index + 1) % RESOLUTIONS.len())];
+    }
+
+    pub fn mode_label(&self) -> &'static str {
+        if self.fullscreen { "FULLSCREEN" } else { "WINDOWED" }
+    }
+
+    pub fn resolution_label(&self) -> String {
+        format!("{} x {}", self.resolution.0, self.resolution.1)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+        ron::from_str(&text).map_err(|e| format!("{} is not a display settings file: {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("can't encode display settings: {e}"))?;
+        fs::write(path, text).map_err(|e| format!("can't write {}: {e}", path.display()))
+    }
+
+    // The saved settings, or the defaults the first time
+    pub fn load_or_default() -> Self {
+        let path = Path::new(DISPLAY_FILE);
+        if !path.exists() {
+            return Self::default();
+        }
+        Self::load(path).unwrap_or_else(|error| {
+            warn!("Couldn't load display settings, using the defaults: {error}");
+            Self::default()
+        })
+    }
+}
+
+pub fn apply_display_settings(settings: Res<DisplaySettings>, mut window_query: Query<&mut Window, With<PrimaryWindow>>) {
+    if let Ok(mut window) = window_query.single_mut() {
+        window.mode = settings.mode();
+        if !settings.fullscreen {
+            window.resolution.set(settings.resolution.0 as f32, settings.resolution.1 as f32);
+        }
+    }
+}
+
+// Images and meshes for the rink, puck, paddles and goals, added as they're
+// spawned so the simulation itself never needs a renderer
+#[allow(clippy::too_many_arguments)]
+pub fn add_visuals(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut rink_query: Query<&mut Sprite, (Added<Rink>, Without<Puck>)>,
+    mut puck_query: Query<&mut Sprite, Added<Puck>>,
+    paddle_query: Query<(Entity, &Paddle), Added<Paddle>>,
+    goal_query: Query<Entity, Added<Goal>>,
+    post_query: Query<Entity, Added<GoalPost>>,
+) {
+    for mut sprite in &mut rink_query {
+        sprite.image = asset_server.load("rink.png");
+    }
+    for mut sprite in &mut puck_query {
+        sprite.image = asset_server.load("puck.png");
+    }
+    for (entity, paddle) in &paddle_query {
+        let color = match paddle.side {
+            Side::Left => Color::srgb(1.0, 0.0, 0.0), // Red
+            Side::Right => Color::srgb(0.0, 0.0, 1.0), // Blue
+        };
+        commands.entity(entity).insert((
+            Mesh2d(meshes.add(Circle::new(config.paddle_radius))),
+            MeshMaterial2d(materials.add(color)),
+        ));
+    }
+    for entity in &goal_query {
+        commands.entity(entity).insert((
+            Mesh2d(meshes.add(Rectangle::new(config.goal_depth * 2.0, config.goal_width))),
+            MeshMaterial2d(materials.add(Color::srgb(0.15, 0.15, 0.15))),
+        ));
+    }
+    for entity in &post_query {
+        commands.entity(entity).insert((
+            Mesh2d(meshes.add(Circle::new(config.goal_post_radius))),
+            MeshMaterial2d(materials.add(Color::srgb(0.8, 0.8, 0.8))),
+        ));
+    }
+}
+
+// Redraws the paddles and goals at the sizes in a changed config
+#[allow(clippy::type_complexity)]
+pub fn resize_meshes(
+    config: Res<GameConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut paddle_query: Query<&mut Mesh2d, With<Paddle>>,
+    mut goal_query: Query<&mut Mesh2d, (With<Goal>, Without<Paddle>)>,
+    mut post_query: Query<&mut Mesh2d, (With<GoalPost>, Without<Paddle>, Without<Goal>)>,
+) {
+    for mut mesh in &mut paddle_query {
+        mesh.0 = meshes.add(Circle::new(config.paddle_radius));
+    }
+    for mut mesh in &mut goal_query {
+        mesh.0 = meshes.add(Rectangle::new(config.goal_depth * 2.0, config.goal_width));
+    }
+    for mut mesh in &mut post_query {
+        mesh.0 = meshes.add(Circle::new(config.goal_post_radius));
+    }
+}
+
+// Black bars over everything outside the rink, one off each edge
+pub fn spawn_letterbox(mut commands: Commands) {
+    for edge in [Vec2::NEG_X, Vec2::X, Vec2::NEG_Y, Vec2::Y] {
+        commands.spawn((
+            Sprite {
+                color: Color::BLACK,
+                custom_size: Some(Vec2::splat(LETTERBOX_SIZE)),
+                ..default()
+            },
+            Transform::from_xyz(0.0, 0.0, 10.0),
+            Letterbox(edge),
+        ));
+    }
+}
+
+// The camera always shows the whole rink, and the bars cover the rest
+pub fn fit_camera_to_rink(
+    config: Res<GameConfig>,
+    mut camera_query: Query<&mut Projection, With<Camera2d>>,
+    mut letterbox_query: Query<(&mut Transform, &Letterbox)>,
+) {
+    let rink = Vec2::new(config.rink_width, config.rink_height);
+    for mut projection in &mut camera_query {
+        *projection = Projection::Orthographic(OrthographicProjection {
+            scaling_mode: ScalingMode::AutoMin {
+                min_width: rink.x,
+                min_height: rink.y,
+            },
+            ..OrthographicProjection::default_2d()
+        });
+    }
+    for (mut transform, letterbox) in &mut letterbox_query {
+        let offset = letterbox.0 * (rink + Vec2::splat(LETTERBOX_SIZE)) / 2.0;
+        transform.translation = offset.extend(transform.translation.z);
+    }
+}
+
+// Scales the menus and scoreboard with the window, as the rink is
+pub fn scale_ui(window_query: Query<&Window, With<PrimaryWindow>>, mut ui_scale: ResMut<UiScale>) {
+    let Ok(window) = window_query.single() else {
+        return;
+    };
+    let scale = (window.size() / UI_REFERENCE_SIZE).min_element();
+    if scale > 0.0 && ui_scale.0 != scale {
+        ui_scale.0 = scale;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn handle_settings_button(
+    mut query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<SettingsButton>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, mut color) in &mut query {
+        match *interaction {
+            Interaction::Pressed => next_state.set(GameState::Settings),
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+}
+
+// Settings Systems
+pub fn setup_settings_screen(mut commands: Commands, display: Res<DisplaySettings>) {
+    let heading = |parent: &mut ChildSpawnerCommands, label: &str| {
+        parent.spawn((
+            Text::new(label),
+            TextFont {
+                font_size: 24.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            TextLayout::new_with_justify(Justify::Center),
+            Node {
+                width: Val::Px(170.0),
+                ..default()
+            },
+        ));
+    };
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(8.0),
+            ..default()
+        },
+        SettingsUI,
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::new("SETTINGS"),
+            TextFont {
+                font_size: 60.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            Node {
+                margin: UiRect::bottom(Val::Px(10.0)),
+                ..default()
+            },
+        ));
+
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(10.0),
+            ..default()
+        }).with_children(|parent| {
+            heading(parent, "WINDOW");
+            spawn_option_button(parent, display.mode_label(), Color::srgb(0.35, 0.35, 0.35), WindowModeButton);
+        });
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(10.0),
+            ..default()
+        }).with_children(|parent| {
+            heading(parent, "RESOLUTION");
+            spawn_option_button(parent, &display.resolution_label(), Color::srgb(0.35, 0.35, 0.35), ResolutionButton);
+        });
+
+        parent.spawn((
+            Text::new("The resolution is the window size when not fullscreen"),
+            TextFont {
+                font_size: 18.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            Node {
+                margin: UiRect::vertical(Val::Px(10.0)),
+                ..default()
+            },
+        ));
+
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            ..default()
+        }).with_children(|parent| {
+            spawn_option_button(parent, "CONTROLS", Color::srgb(0.35, 0.35, 0.35), ControlsButton);
+            spawn_option_button(parent, "BACK", Color::srgb(0.8, 0.2, 0.2), SettingsBackButton);
+        });
+    });
+}
+
+#[allow(clippy::type_complexity)]
+pub fn handle_settings_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut mode_query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<WindowModeButton>)>,
+    mut resolution_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ResolutionButton>, Without<WindowModeButton>),
+    >,
+    back_query: Query<&Interaction, (Changed<Interaction>, With<SettingsBackButton>)>,
+    mut display: ResMut<DisplaySettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    bindings: Res<KeyBindings>,
+) {
+    if bindings.just_pressed(&keyboard_input, Action::Pause)
+        || back_query.iter().any(|interaction| *interaction == Interaction::Pressed)
+    {
+        next_state.set(GameState::StartScreen);
+        return;
+    }
+
+    let mut changed = false;
+    for (interaction, mut color) in &mut mode_query {
+        match *interaction {
+            Interaction::Pressed => {
+                display.fullscreen = !display.fullscreen;
+                changed = true;
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+    for (interaction, mut color) in &mut resolution_query {
+        match *interaction {
+            Interaction::Pressed => {
+                display.cycle_resolution();
+                changed = true;
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+
+    if changed && let Err(error) = display.save(Path::new(DISPLAY_FILE)) {
+        error!("Couldn't save display settings: {error}");
+    }
+}
+
+pub fn update_settings_display(
+    display: Res<DisplaySettings>,
+    mode_query: Query<&Children, With<WindowModeButton>>,
+    resolution_query: Query<&Children, With<ResolutionButton>>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !display.is_changed() {
+        return;
+    }
+    for children in &mode_query {
+        let mut texts = text_query.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            *text = Text::new(display.mode_label());
+        }
+    }
+    for children in &resolution_query {
+        let mut texts = text_query.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            *text = Text::new(display.resolution_label());
+        }
+    }
+}
+
+pub fn cleanup_settings_screen(mut commands: Commands, query: Query<Entity, With<SettingsUI>>) {
+    for entity in &query {
+        commands.entity(entity).despawn();
+    }
+}
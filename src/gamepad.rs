@@ -0,0 +1,403 @@
+use bevy::prelude::*;
+use crate::bindings::{Action, KeyBindings, key_label};
+use crate::components::*;
+use crate::resources::*;
+use crate::systems::spawn_option_button;
+
+// Controllers. Each side of the table can have a controller as well as its keys,
+// and any controller can find its way around the menus with the D-pad or stick.
+
+const STICK_PUSH: f32 = 0.5; // How far the stick goes before it counts as a menu move
+
+// Which controller plays which side. The first two connected get the left then
+// the right side, after that they are moved on the controllers screen.
+#[derive(Resource, Default)]
+pub struct GamepadAssignments {
+    pub left: Option<Entity>,
+    pub right: Option<Entity>,
+}
+
+impl GamepadAssignments {
+    pub fn get(&self, side: Side) -> Option<Entity> {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+        }
+    }
+
+    pub fn side_of(&self, gamepad: Entity) -> Option<Side> {
+        if self.left == Some(gamepad) {
+            Some(Side::Left)
+        } else if self.right == Some(gamepad) {
+            Some(Side::Right)
+        } else {
+            None
+        }
+    }
+
+    // Whoever had the side before is left without one
+    fn assign(&mut self, gamepad: Entity, side: Option<Side>) {
+        self.unassign(gamepad);
+        match side {
+            Some(Side::Left) => self.left = Some(gamepad),
+            Some(Side::Right) => self.right = Some(gamepad),
+            None => {}
+        }
+    }
+
+    fn unassign(&mut self, gamepad: Entity) {
+        if self.left == Some(gamepad) {
+            self.left = None;
+        }
+        if self.right == Some(gamepad) {
+            self.right = None;
+        }
+    }
+}
+
+// Stick and D-pad together, up to length 1 for full speed
+pub fn gamepad_direction(gamepad: &Gamepad) -> Vec2 {
+    (gamepad.left_stick() + gamepad.dpad()).clamp_length_max(1.0)
+}
+
+// A single step on the D-pad or a fresh push of the stick, for moving around menus
+fn menu_step(gamepad: &Gamepad, stick_held: bool) -> Option<Vec2> {
+    let dpad = [
+        (GamepadButton::DPadUp, Vec2::Y),
+        (GamepadButton::DPadDown, Vec2::NEG_Y),
+        (GamepadButton::DPadLeft, Vec2::NEG_X),
+        (GamepadButton::DPadRight, Vec2::X),
+    ];
+    if let Some((_, step)) = dpad.iter().find(|(button, _)| gamepad.just_pressed(*button)) {
+        return Some(*step);
+    }
+    let stick = gamepad.left_stick();
+    if stick_held || stick.length() < STICK_PUSH {
+        return None;
+    }
+    Some(if stick.x.abs() > stick.y.abs() { Vec2::new(stick.x.signum(), 0.0) } else { Vec2::new(0.0, stick.y.signum()) })
+}
+
+fn stick_pushed(gamepad: &Gamepad) -> bool {
+    gamepad.left_stick().length() >= STICK_PUSH
+}
+
+pub fn assign_gamepads(
+    mut assignments: ResMut<GamepadAssignments>,
+    connected: Query<(Entity, Option<&Name>), Added<Gamepad>>,
+    gamepads: Query<(), With<Gamepad>>,
+) {
+    for side in [Side::Left, Side::Right] {
+        if let Some(gamepad) = assignments.get(side)
+            && !gamepads.contains(gamepad)
+        {
+            info!("Controller on the {side:?} side disconnected");
+            assignments.unassign(gamepad);
+        }
+    }
+    for (gamepad, name) in &connected {
+        if assignments.side_of(gamepad).is_some() {
+            continue;
+        }
+        let side = if assignments.left.is_none() {
+            Side::Left
+        } else if assignments.right.is_none() {
+            Side::Right
+        } else {
+            continue;
+        };
+        info!("{} plays the {side:?} side", name.map_or("Controller", |name| name.as_str()));
+        assignments.assign(gamepad, Some(side));
+    }
+}
+
+// Menu Navigation
+
+// The button a controller has picked out on the screen. South presses it, and
+// it's let go again the frame after, like a click.
+#[derive(Resource, Default)]
+pub struct MenuFocus {
+    focused: Option<Entity>,
+    pressed: Option<Entity>,
+    stick_held: bool,
+}
+
+// Runs after the UI has read the mouse, so the buttons' own systems see a press
+// from a controller just like a click
+pub fn navigate_menus(
+    mut commands: Commands,
+    mut focus: ResMut<MenuFocus>,
+    gamepads: Query<&Gamepad>,
+    mut buttons: Query<(Entity, &mut Interaction, &UiGlobalTransform, &InheritedVisibility), With<Button>>,
+) {
+    if let Some(pressed) = focus.pressed.take()
+        && let Ok((_, mut interaction, _, _)) = buttons.get_mut(pressed)
+    {
+        interaction.set_if_neq(Interaction::None);
+    }
+
+    // Screen positions, with y turned around so up is up
+    let visible: Vec<(Entity, Vec2)> = buttons
+        .iter()
+        .filter(|(_, _, _, visibility)| visibility.get())
+        .map(|(entity, _, transform, _)| (entity, transform.translation * Vec2::new(1.0, -1.0)))
+        .collect();
+    let current = focus.focused.and_then(|focused| visible.iter().find(|(entity, _)| *entity == focused).copied());
+
+    let mut step = None;
+    let mut select = false;
+    for gamepad in &gamepads {
+        step = step.or(menu_step(gamepad, focus.stick_held));
+        select |= gamepad.just_pressed(GamepadButton::South);
+    }
+    focus.stick_held = gamepads.iter().any(stick_pushed);
+
+    let next = match (current, step) {
+        // The first move picks the button at the top left
+        (None, Some(_)) => visible.iter().copied().min_by(|(_, a), (_, b)| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x))),
+        // The nearest button that way, favouring ones in line with this one
+        (Some((_, from)), Some(step)) => visible
+            .iter()
+            .copied()
+            .filter(|(_, to)| (*to - from).dot(step) > 1.0)
+            .min_by(|(_, a), (_, b)| {
+                let cost = |to: Vec2| {
+                    let offset = to - from;
+                    offset.dot(step) + 2.0 * offset.perp_dot(step).abs()
+                };
+                cost(*a).total_cmp(&cost(*b))
+            })
+            .or(current),
+        (current, None) => current,
+    };
+
+    let next = next.map(|(entity, _)| entity);
+    if next != focus.focused {
+        if let Some(old) = focus.focused
+            && buttons.contains(old)
+        {
+            commands.entity(old).remove::<Outline>();
+        }
+        if let Some(new) = next {
+            commands.entity(new).insert(Outline::new(Val::Px(3.0), Val::Px(2.0), Color::WHITE));
+        }
+        focus.focused = next;
+    }
+
+    if select
+        && let Some(focused) = focus.focused
+        && let Ok((_, mut interaction, _, _)) = buttons.get_mut(focused)
+    {
+        *interaction = Interaction::Pressed;
+        focus.pressed = Some(focused);
+    }
+}
+
+// Controllers Systems
+#[allow(clippy::type_complexity)]
+pub fn handle_controllers_button(
+    mut query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<ControllersButton>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, mut color) in &mut query {
+        match *interaction {
+            Interaction::Pressed => next_state.set(GameState::Controllers),
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+}
+
+pub fn setup_controllers_screen(mut commands: Commands, bindings: Res<KeyBindings>) {
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ControllersUI,
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::new("CONTROLLERS"),
+            TextFont {
+                font_size: 60.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            Node {
+                margin: UiRect::bottom(Val::Px(30.0)),
+                ..default()
+            },
+        ));
+
+        // Column headings, with each controller listed under its side
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            ..default()
+        }).with_children(|parent| {
+            for (label, color) in [
+                ("RED", Color::srgb(0.8, 0.2, 0.2)),
+                ("NO SIDE", Color::BLACK),
+                ("BLUE", Color::srgb(0.2, 0.2, 0.8)),
+            ] {
+                parent.spawn((
+                    Text::new(label),
+                    TextFont {
+                        font_size: 28.0,
+                        ..default()
+                    },
+                    TextColor(color),
+                    TextLayout::new_with_justify(Justify::Center),
+                    Node {
+                        width: Val::Px(220.0),
+                        ..default()
+                    },
+                ));
+            }
+        });
+        parent.spawn((
+            Node {
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(8.0),
+                margin: UiRect::vertical(Val::Px(20.0)),
+                min_height: Val::Px(80.0),
+                ..default()
+            },
+            ControllerList,
+        ));
+
+        parent.spawn((
+            Text::new(format!(
+                "Push left or right on a controller to pick its side\n{} / B: Back",
+                key_label(bindings.key(Action::Pause)),
+            )),
+            TextFont {
+                font_size: 22.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            TextLayout::new_with_justify(Justify::Center),
+            Node {
+                margin: UiRect::bottom(Val::Px(20.0)),
+                ..default()
+            },
+        ));
+        spawn_option_button(parent, "BACK", Color::srgb(0.8, 0.2, 0.2), ControllersBackButton);
+    });
+}
+
+pub fn handle_controllers_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<(Entity, &Gamepad)>,
+    back_query: Query<&Interaction, (Changed<Interaction>, With<ControllersBackButton>)>,
+    mut assignments: ResMut<GamepadAssignments>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut sticks_held: Local<Vec<Entity>>,
+    bindings: Res<KeyBindings>,
+) {
+    let back = bindings.just_pressed(&keyboard_input, Action::Pause)
+        || back_query.iter().any(|interaction| *interaction == Interaction::Pressed)
+        || gamepads.iter().any(|(_, gamepad)| gamepad.just_pressed(GamepadButton::East));
+    if back {
+        next_state.set(GameState::Controls);
+        return;
+    }
+
+    for (entity, gamepad) in &gamepads {
+        let held = sticks_held.contains(&entity);
+        if stick_pushed(gamepad) != held {
+            if held {
+                sticks_held.retain(|held| *held != entity);
+            } else {
+                sticks_held.push(entity);
+            }
+        }
+        let Some(step) = menu_step(gamepad, held).filter(|step| step.x != 0.0) else {
+            continue;
+        };
+        // One step at a time, from the red side through no side to the blue side
+        let side = match (assignments.side_of(entity), step.x < 0.0) {
+            (None, true) | (Some(Side::Left), true) => Some(Side::Left),
+            (None, false) | (Some(Side::Right), false) => Some(Side::Right),
+            (Some(_), _) => None,
+        };
+        assignments.assign(entity, side);
+    }
+}
+
+// Rebuilt whenever a controller comes, goes or changes side
+pub fn update_controllers_display(
+    mut commands: Commands,
+    assignments: Res<GamepadAssignments>,
+    gamepads: Query<(Entity, Option<&Name>), With<Gamepad>>,
+    list_query: Query<(Entity, Option<&Children>), With<ControllerList>>,
+    mut shown: Local<Vec<(Entity, Option<Side>)>>,
+) {
+    let Ok((list, rows)) = list_query.single() else {
+        return;
+    };
+    let current: Vec<(Entity, Option<Side>)> = gamepads.iter().map(|(entity, _)| (entity, assignments.side_of(entity))).collect();
+    if *shown == current && rows.is_some_and(|rows| !rows.is_empty()) {
+        return;
+    }
+    *shown = current;
+
+    if let Some(rows) = rows {
+        for row in rows {
+            commands.entity(*row).despawn();
+        }
+    }
+    commands.entity(list).with_children(|parent| {
+        if gamepads.is_empty() {
+            parent.spawn((
+                Text::new("No controllers connected"),
+                TextFont {
+                    font_size: 22.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.3, 0.3, 0.3)),
+                TextLayout::new_with_justify(Justify::Center),
+                Node {
+                    width: Val::Px(660.0),
+                    ..default()
+                },
+            ));
+        }
+        for (entity, name) in &gamepads {
+            let column = match assignments.side_of(entity) {
+                Some(Side::Left) => 0.0,
+                None => 1.0,
+                Some(Side::Right) => 2.0,
+            };
+            parent.spawn(Node {
+                width: Val::Px(660.0),
+                ..default()
+            }).with_children(|parent| {
+                parent.spawn((
+                    Text::new(name.map_or("Controller", |name| name.as_str())),
+                    TextFont {
+                        font_size: 22.0,
+                        ..default()
+                    },
+                    TextColor(Color::BLACK),
+                    TextLayout::new_with_justify(Justify::Center),
+                    Node {
+                        width: Val::Px(220.0),
+                        margin: UiRect::left(Val::Px(220.0 * column)),
+                        ..default()
+                    },
+                ));
+            });
+        }
+    });
+}
+
+pub fn cleanup_controllers_screen(mut commands: Commands, query: Query<Entity, With<ControllersUI>>) {
+    for entity in &query {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use crate::components::*;
+use crate::constants::*;
+use crate::resources::*;
+use crate::snapshot::Resimulating;
+
+#[derive(Clone, Copy)]
+pub struct RecentFrame {
+    pub puck: Vec2,
+    pub left: Vec2,
+    pub right: Vec2,
+}
+
+// The last GOAL_REPLAY_SECONDS of play, one frame per tick
+#[derive(Resource, Default)]
+pub struct RecentFrames {
+    pub frames: VecDeque<RecentFrame>,
+    pub capacity: usize,
+}
+
+// An instant replay being shown over the serve delay
+#[derive(Resource)]
+pub struct GoalReplay {
+    pub frames: Vec<RecentFrame>,
+}
+
+pub fn reset_recent_frames(mut commands: Commands, settings: Res<MatchSettings>) {
+    commands.insert_resource(RecentFrames {
+        frames: VecDeque::new(),
+        capacity: ((GOAL_REPLAY_SECONDS * settings.tick_rate) as usize).max(1),
+    });
+}
+
+// Runs after the puck moves and before goals are checked, so the last frame is
+// the puck crossing the line. Ticks being re-simulated were recorded already.
+pub fn record_recent_frames(
+    mut recent: ResMut<RecentFrames>,
+    puck_query: Query<&Position, With<Puck>>,
+    paddle_query: Query<(&Position, &Paddle)>,
+    resimulating: Option<Res<Resimulating>>,
+) {
+    let Ok(puck) = puck_query.single() else {
+        return;
+    };
+    if resimulating.is_some() {
+        return;
+    }
+    let mut frame = RecentFrame {
+        puck: puck.0,
+        left: Vec2::ZERO,
+        right: Vec2::ZERO,
+    };
+    for (position, paddle) in &paddle_query {
+        match paddle.side {
+            Side::Left => frame.left = position.0,
+            Side::Right => frame.right = position.0,
+        }
+    }
+
+    if recent.frames.len() >= recent.capacity {
+        recent.frames.pop_front();
+    }
+    recent.frames.push_back(frame);
+}
+
+// Goals scored again while ticks are re-simulated were already replayed the
+// first time round, and the recent frames no longer lead up to them
+pub fn start_goal_replay(
+    mut commands: Commands,
+    mut goals: MessageReader<GoalScored>,
+    mut recent: ResMut<RecentFrames>,
+    banner_query: Query<Entity, With<GoalReplayText>>,
+    resimulating: Option<Res<Resimulating>>,
+) {
+    if goals.read().count() == 0 || recent.frames.is_empty() || resimulating.is_some() {
+        return;
+    }
+
+    commands.insert_resource(GoalReplay {
+        frames: recent.frames.drain(..).collect(),
+    });
+    if !banner_query.is_empty() {
+        return;
+    }
+    commands.spawn((
+        Text::new("REPLAY"),
+        TextFont {
+            font_size: 48.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.8, 0.1, 0.1)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(90.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        TextLayout::new_with_justify(Justify::Center),
+        GoalReplayText,
+        GameUI,
+    ));
+}
+
+// Draws the replay over the interpolated positions. The frames are fitted to the
+// serve delay and eased out, so the run-up plays quickly and the goal itself in
+// slow motion.
+pub fn play_goal_replay(
+    commands: Commands,
+    replay: Res<GoalReplay>,
+    timer: Res<PuckDelayTimer>,
+    fixed_time: Res<Time<Fixed>>,
+    mut puck_query: Query<&mut Transform, With<Puck>>,
+    mut paddle_query: Query<(&mut Transform, &Paddle), Without<Puck>>,
+    banner_query: Query<Entity, With<GoalReplayText>>,
+) {
+    let duration = timer.0.duration().as_secs_f32();
+    let elapsed = timer.0.elapsed_secs() + fixed_time.overstep_fraction() * fixed_time.timestep().as_secs_f32();
+    if timer.0.is_finished() || elapsed >= duration {
+        stop_goal_replay(commands, banner_query);
+        return;
+    }
+
+    let progress = 1.0 - (1.0 - elapsed / duration).powi(2);
+    let position = progress * (replay.frames.len() - 1) as f32;
+    let index = position as usize;
+    let from = replay.frames[index];
+    let to = replay.frames[(index + 1).min(replay.frames.len() - 1)];
+    let alpha = position.fract();
+
+    for mut transform in &mut puck_query {
+        transform.translation = from.puck.lerp(to.puck, alpha).extend(transform.translation.z);
+    }
+    for (mut transform, paddle) in &mut paddle_query {
+        let drawn = match paddle.side {
+            Side::Left => from.left.lerp(to.left, alpha),
+            Side::Right => from.right.lerp(to.right, alpha),
+        };
+        transform.translation = drawn.extend(transform.translation.z);
+    }
+}
+
+// Either player can skip the replay, from the keyboard or a controller. Start is
+// left to pause, like the keys kept away from the Pause binding.
+pub fn skip_goal_replay(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    commands: Commands,
+    banner_query: Query<Entity, With<GoalReplayText>>,
+) {
+    let skip_pressed = keyboard_input.any_just_pressed([KeyCode::Space, KeyCode::Enter])
+        || gamepads.iter().any(|gamepad| gamepad.any_just_pressed([GamepadButton::South, GamepadButton::East]));
+    if skip_pressed {
+        stop_goal_replay(commands, banner_query);
+    }
+}
+
+pub fn stop_goal_replay(mut commands: Commands, banner_query: Query<Entity, With<GoalReplayText>>) {
+    commands.remove_resource::<GoalReplay>();
+    for entity in &banner_query {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,26 @@
+
+// Air hockey as a Bevy plugin. Add AirHockeyPlugin to an app to get the whole
+// game, or see main.rs for it running on its own.
+
+pub mod ai;
+pub mod bindings;
+pub mod components;
+pub mod config;
+pub mod constants;
+pub mod display;
+pub mod gamepad;
+pub mod goal_replay;
+pub mod lobby;
+pub mod mouse;
+pub mod net;
+pub mod physics;
+pub mod plugins;
+pub mod replay;
+pub mod replay_viewer;
+pub mod resources;
+pub mod rollback;
+pub mod snapshot;
+pub mod spectate;
+pub mod systems;
+
+pub use plugins::AirHockeyPlugin;
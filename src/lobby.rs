@@ -0,0 +1,467 @@
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::bindings::{Action, KeyBindings};
+use crate::components::*;
+use crate::constants::*;
+use crate::net::*;
+use crate::resources::*;
+use crate::rollback::RollbackSession;
+use crate::systems::spawn_option_button;
+
+// Finding a game on the local network. Players in the lobby broadcast a request
+// every second, and a game hosted from the lobby answers with its name and rules.
+// Once a player has joined, the match starts when both of them are ready.
+
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize)]
+enum LobbyMessage {
+    Discover { version: u32, sent_at: f64 }, // Echoed back, to time the ping
+    Game {
+        id: u64, // The same game is heard over loopback and the network
+        name: String,
+        port: u16, // Where to join it
+        rollback: bool,
+        settings: MatchSettings,
+        full: bool,
+        sent_at: f64,
+    },
+}
+
+struct LobbyGame {
+    id: u64,
+    name: String,
+    address: SocketAddr,
+    rollback: bool,
+    settings: MatchSettings,
+    full: bool,
+    ping: Duration,
+    last_heard: Duration,
+}
+
+impl LobbyGame {
+    fn label(&self) -> String {
+        let rules = match self.settings.match_length {
+            Some(_) => self.settings.rule_label(Rule::MatchLength),
+            None => self.settings.rule_label(Rule::TargetScore),
+        };
+        let status = if self.full { "FULL".to_string() } else { format!("{} MS", self.ping.as_millis()) };
+        format!(
+            "{}  -  {}, {}, {}  -  {}",
+            self.name,
+            rules,
+            self.settings.rule_label(Rule::Physics),
+            netcode_label(self.rollback),
+            status,
+        )
+    }
+}
+
+#[derive(Resource)]
+pub struct Lobby {
+    socket: Option<UdpSocket>, // Looks for games, or answers as the host
+    name: String,
+    hosting: Option<u64>, // Id of the game hosted from here
+    joined: Option<String>, // Name of the game joined
+    games: Vec<LobbyGame>,
+    last_discover: Option<Duration>,
+    pub ready: bool,
+    pub peer_ready: bool,
+}
+
+fn bind_discovery(port: u16) -> std::io::Result<UdpSocket> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+    socket.set_nonblocking(true)?;
+    socket.set_broadcast(true)?;
+    Ok(socket)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn handle_lobby_button(
+    mut query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<LobbyButton>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, mut color) in &mut query {
+        match *interaction {
+            Interaction::Pressed => next_state.set(GameState::Lobby),
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+}
+
+// Lobby Systems
+pub fn setup_lobby(mut commands: Commands, options: Res<LaunchOptions>) {
+    let socket = match bind_discovery(0) {
+        Ok(socket) => Some(socket),
+        Err(error) => {
+            error!("Couldn't look for games: {error}");
+            None
+        }
+    };
+    commands.insert_resource(Lobby {
+        socket,
+        name: options.player_name(),
+        hosting: None,
+        joined: None,
+        games: Vec::new(),
+        last_discover: None,
+        ready: false,
+        peer_ready: false,
+    });
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        LobbyUI,
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::new("LAN LOBBY"),
+            TextFont {
+                font_size: 60.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            Node {
+                margin: UiRect::bottom(Val::Px(30.0)),
+                ..default()
+            },
+        ));
+
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            ..default()
+        }).with_children(|parent| {
+            spawn_option_button(parent, "HOST GAME", Color::srgb(0.35, 0.35, 0.35), LobbyHostButton);
+            spawn_option_button(parent, netcode_label(options.rollback), Color::srgb(0.35, 0.35, 0.35), NetcodeButton);
+            spawn_option_button(parent, "READY", Color::srgb(0.35, 0.35, 0.35), LobbyReadyButton);
+            spawn_option_button(parent, "BACK", Color::srgb(0.8, 0.2, 0.2), LobbyBackButton);
+        });
+
+        parent.spawn((
+            Text::new(""),
+            TextFont {
+                font_size: 24.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            TextLayout::new_with_justify(Justify::Center),
+            Node {
+                margin: UiRect::vertical(Val::Px(20.0)),
+                ..default()
+            },
+            LobbyStatusText,
+        ));
+
+        parent.spawn((
+            Node {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(6.0),
+                ..default()
+            },
+            LobbyGameList,
+        ));
+    });
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn handle_lobby_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut host_query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<LobbyHostButton>)>,
+    mut ready_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<LobbyReadyButton>, Without<LobbyHostButton>),
+    >,
+    back_query: Query<&Interaction, (Changed<Interaction>, With<LobbyBackButton>)>,
+    mut game_query: Query<
+        (&Interaction, &mut BackgroundColor, &LobbyGameButton),
+        (Changed<Interaction>, Without<LobbyHostButton>, Without<LobbyReadyButton>),
+    >,
+    mut lobby: ResMut<Lobby>,
+    session: Option<Res<NetSession>>,
+    mut commands: Commands,
+    options: Res<LaunchOptions>,
+    mut settings: ResMut<MatchSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    bindings: Res<KeyBindings>,
+) {
+    if bindings.just_pressed(&keyboard_input, Action::Pause)
+        || back_query.iter().any(|interaction| *interaction == Interaction::Pressed)
+    {
+        next_state.set(GameState::StartScreen);
+        return;
+    }
+
+    for (interaction, mut color) in &mut host_query {
+        match *interaction {
+            Interaction::Pressed => {
+                if session.is_some() || !host_session(&mut commands, &options, &mut settings) {
+                    continue;
+                }
+                // Only one game per machine can be found, the port is taken after that
+                lobby.socket = match bind_discovery(DISCOVERY_PORT) {
+                    Ok(socket) => Some(socket),
+                    Err(error) => {
+                        warn!("Another game is hosting on this machine, this one can only be joined directly: {error}");
+                        None
+                    }
+                };
+                lobby.hosting = Some(rand::random());
+                lobby.games.clear();
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+
+    for (interaction, mut color) in &mut ready_query {
+        match *interaction {
+            Interaction::Pressed => {
+                if session.is_some() {
+                    lobby.ready = !lobby.ready;
+                }
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+
+    for (interaction, mut color, button) in &mut game_query {
+        match *interaction {
+            Interaction::Pressed if session.is_none() => {}
+            Interaction::Hovered => {
+                *color = Color::srgb(0.25, 0.25, 0.85).into();
+                continue;
+            }
+            _ => {
+                *color = Color::srgb(0.2, 0.2, 0.8).into();
+                continue;
+            }
+        }
+        let Some(game) = lobby.games.iter().find(|game| game.id == button.0 && !game.full) else {
+            continue;
+        };
+        if join_session(&mut commands, &options, &settings, game.address, game.rollback) {
+            lobby.joined = Some(game.name.clone());
+            lobby.socket = None;
+            lobby.games.clear();
+        }
+    }
+}
+
+// Looks for games, or answers players looking for this one
+pub fn run_discovery(
+    mut lobby: ResMut<Lobby>,
+    session: Option<Res<NetSession>>,
+    rollback: Option<Res<RollbackSession>>,
+    settings: Res<MatchSettings>,
+    options: Res<LaunchOptions>,
+    real_time: Res<Time<Real>>,
+) {
+    let now = real_time.elapsed();
+    let lobby = &mut *lobby;
+    let Some(socket) = &lobby.socket else {
+        return;
+    };
+
+    let mut buffer = [0u8; 1024];
+    loop {
+        let (length, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(_) => continue,
+        };
+        match decode::<LobbyMessage>(&buffer[..length]) {
+            Some(LobbyMessage::Discover { version, sent_at }) if version == NET_VERSION => {
+                let Some(id) = lobby.hosting else {
+                    continue;
+                };
+                let answer = LobbyMessage::Game {
+                    id,
+                    name: lobby.name.clone(),
+                    port: options.port.unwrap_or(DEFAULT_PORT),
+                    rollback: rollback.is_some(),
+                    settings: settings.clone(),
+                    full: session.as_ref().is_some_and(|session| session.peer.is_some()),
+                    sent_at,
+                };
+                if let Some(packet) = encode(&answer) {
+                    let _ = socket.send_to(&packet, from);
+                }
+            }
+            Some(LobbyMessage::Game { id, name, port, rollback, settings, full, sent_at }) => {
+                if lobby.hosting.is_some() {
+                    continue;
+                }
+                let game = LobbyGame {
+                    id,
+                    name,
+                    address: SocketAddr::new(from.ip(), port),
+                    rollback,
+                    settings,
+                    full,
+                    ping: now.saturating_sub(Duration::from_secs_f64(sent_at)),
+                    last_heard: now,
+                };
+                match lobby.games.iter_mut().find(|known| known.id == id) {
+                    // Heard over loopback and the network at once, keep the first
+                    Some(known) if known.last_heard == now => {}
+                    Some(known) => *known = game,
+                    None => lobby.games.push(game),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if lobby.hosting.is_some() {
+        return;
+    }
+    lobby.games.retain(|game| now.saturating_sub(game.last_heard) <= TIMEOUT);
+    if lobby.last_discover.is_some_and(|last| now.saturating_sub(last) < DISCOVERY_INTERVAL) {
+        return;
+    }
+    lobby.last_discover = Some(now);
+    let Some(packet) = encode(&LobbyMessage::Discover { version: NET_VERSION, sent_at: now.as_secs_f64() }) else {
+        return;
+    };
+    // Loopback as well, for a game on this machine when there's no network
+    for address in [Ipv4Addr::BROADCAST, Ipv4Addr::LOCALHOST] {
+        let _ = socket.send_to(&packet, (address, DISCOVERY_PORT));
+    }
+}
+
+// The host starts the match once both players are ready. The other player
+// follows when the host's start message arrives.
+pub fn start_lobby_match(
+    mut lobby: ResMut<Lobby>,
+    mut session: ResMut<NetSession>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if lobby.hosting.is_none() {
+        return;
+    }
+    // A player who vanished without saying goodbye frees the game up again
+    if session.lost {
+        lobby.peer_ready = false;
+        session.drop_peer();
+    }
+    if session.peer.is_some() && lobby.ready && lobby.peer_ready {
+        next_state.set(GameState::Countdown);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_lobby_display(
+    mut commands: Commands,
+    lobby: Res<Lobby>,
+    session: Option<Res<NetSession>>,
+    options: Res<LaunchOptions>,
+    list_query: Query<Entity, With<LobbyGameList>>,
+    button_query: Query<(Entity, &LobbyGameButton, &Children)>,
+    mut status_query: Query<&mut Text, With<LobbyStatusText>>,
+    mut text_query: Query<&mut Text, Without<LobbyStatusText>>,
+) {
+    let ready_label = |ready| if ready { "READY" } else { "NOT READY" };
+    let status = match &session {
+        None if lobby.games.is_empty() => "Looking for games...\nHOST GAME to start one".to_string(),
+        None => "Pick a game to join, or host your own".to_string(),
+        Some(session) if !session.connected => match &lobby.joined {
+            Some(name) => format!("JOINING {name}..."),
+            None => format!(
+                "HOSTING AS {}\nPort {}\n\nWaiting for an opponent...",
+                lobby.name,
+                options.port.unwrap_or(DEFAULT_PORT),
+            ),
+        },
+        Some(session) => {
+            let (red, blue) = match session.local_side {
+                Side::Left => (lobby.ready, lobby.peer_ready),
+                Side::Right => (lobby.peer_ready, lobby.ready),
+            };
+            let you = if session.local_side == Side::Left { "RED" } else { "BLUE" };
+            format!(
+                "RED: {}\nBLUE: {}\n\nYou are {you}, press READY when you are",
+                ready_label(red),
+                ready_label(blue),
+            )
+        }
+    };
+    for mut text in &mut status_query {
+        if text.0 != status {
+            text.0 = status.clone();
+        }
+    }
+
+    // Relabel the buttons in place, unless a game has come or gone
+    let Ok(list) = list_query.single() else {
+        return;
+    };
+    let mut shown: Vec<u64> = button_query.iter().map(|(_, button, _)| button.0).collect();
+    let mut found: Vec<u64> = lobby.games.iter().map(|game| game.id).collect();
+    shown.sort_unstable();
+    found.sort_unstable();
+    if shown == found {
+        for (_, button, children) in &button_query {
+            let Some(game) = lobby.games.iter().find(|game| game.id == button.0) else {
+                continue;
+            };
+            let mut texts = text_query.iter_many_mut(children);
+            while let Some(mut text) = texts.fetch_next() {
+                let label = game.label();
+                if text.0 != label {
+                    text.0 = label;
+                }
+            }
+        }
+        return;
+    }
+
+    for (entity, _, _) in &button_query {
+        commands.entity(entity).despawn();
+    }
+    commands.entity(list).with_children(|parent| {
+        for game in &lobby.games {
+            parent.spawn((
+                Button,
+                Node {
+                    width: Val::Px(700.0),
+                    height: Val::Px(40.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                BackgroundColor(Color::srgb(0.2, 0.2, 0.8)),
+                LobbyGameButton(game.id),
+            )).with_children(|parent| {
+                parent.spawn((
+                    Text::new(game.label()),
+                    TextFont {
+                        font_size: 20.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                ));
+            });
+        }
+    });
+}
+
+pub fn cleanup_lobby(mut commands: Commands, query: Query<Entity, With<LobbyUI>>) {
+    for entity in &query {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<Lobby>();
+}
@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use crate::components::*;
+use crate::config::GameConfig;
+use crate::net::NetSession;
+use crate::resources::*;
+
+// Playing with the mouse, like dragging a real mallet. The paddle heads for the
+// cursor at up to paddle_speed, so it's moved by the same inputs as a key press
+// and can't jump through the puck.
+
+// Where the cursor is on the rink, read once a frame
+#[derive(Resource, Default)]
+pub struct MouseTarget(pub Option<Vec2>);
+
+#[allow(clippy::type_complexity)]
+pub fn handle_mouse_button(
+    mut query: Query<(&Interaction, &mut BackgroundColor, &Children), (Changed<Interaction>, With<MouseControlButton>)>,
+    mut text_query: Query<&mut Text>,
+    mut mouse_control: ResMut<MouseControl>,
+) {
+    for (interaction, mut color, children) in &mut query {
+        match *interaction {
+            Interaction::Pressed => {
+                mouse_control.cycle();
+                let mut texts = text_query.iter_many_mut(children);
+                while let Some(mut text) = texts.fetch_next() {
+                    *text = Text::new(mouse_control.label());
+                }
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+}
+
+// In a networked match the mouse moves this player's paddle, whichever side it's on
+#[allow(clippy::type_complexity)]
+pub fn mark_mouse_paddle(
+    mut commands: Commands,
+    mouse_control: Res<MouseControl>,
+    session: Option<Res<NetSession>>,
+    query: Query<(Entity, &Paddle), (Without<CpuPaddle>, Without<RemotePaddle>)>,
+) {
+    let Some(side) = mouse_control.0 else {
+        return;
+    };
+    let side = session.map_or(side, |session| session.local_side);
+    for (entity, paddle) in &query {
+        if paddle.side == side {
+            commands.entity(entity).insert(MousePaddle);
+        }
+    }
+}
+
+// Through the camera, so it's right wherever the window has been moved or scaled
+pub fn read_mouse_target(
+    mut target: ResMut<MouseTarget>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+) {
+    let Ok(window) = window_query.single() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_query.single() else {
+        return;
+    };
+    // Off the window the paddle stays where the cursor left it
+    if let Some(cursor) = window.cursor_position()
+        && let Ok(position) = camera.viewport_to_world_2d(camera_transform, cursor)
+    {
+        target.0 = Some(position);
+    }
+}
+
+// Every tick, the input that gets the paddle to the cursor soonest
+pub fn follow_mouse(
+    target: Res<MouseTarget>,
+    config: Res<GameConfig>,
+    time: Res<Time>,
+    mut query: Query<(&Position, &mut PaddleInput), With<MousePaddle>>,
+) {
+    let full_speed_step = config.paddle_speed * time.delta_secs();
+    for (position, mut input) in &mut query {
+        input.0 = match target.0 {
+            Some(target) if full_speed_step > 0.0 => ((target - position.0) / full_speed_step).clamp_length_max(1.0),
+            _ => Vec2::ZERO,
+        };
+    }
+}
@@ -0,0 +1,922 @@
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use crate::components::*;
+use crate::bindings::{Action, KeyBindings, key_label};
+use crate::config::{ConfigFile, GameConfig};
+use crate::constants::*;
+use crate::lobby::Lobby;
+use crate::resources::*;
+use crate::rollback::{PeerMessage, RollbackSession};
+use crate::snapshot::{self, WorldSnapshot};
+use crate::systems::{reset_match, spawn_option_button, step_paddle};
+
+// Networked two-player matches. The host runs the simulation with the client's
+// paddle driven by inputs it receives, and sends back the state after every
+// frame. The client predicts its own paddle and draws everything else slightly
+// in the past, between two states from the host.
+
+pub const NET_VERSION: u32 = 4;
+pub const TIMEOUT: Duration = Duration::from_secs(3);
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(250);
+const INTERPOLATION_DELAY_SECONDS: f64 = 0.1;
+const MAX_QUEUED_INPUTS: usize = 6; // Beyond this the host skips ahead to stay responsive
+const MAX_PENDING_INPUTS: usize = 120;
+const INPUTS_PER_PACKET: usize = 32; // Recent inputs are resent, so a lost packet costs nothing
+const MAX_STATES: usize = 64;
+
+#[derive(Serialize, Deserialize)]
+pub enum ClientMessage {
+    Hello { version: u32 },
+    // From a spectator, as its heartbeat, with the match it is following
+    Watch { version: u32, match_id: Option<u64> },
+    Input { first_seq: u64, inputs: Vec<[f32; 2]> },
+    Pause(bool),
+    Rematch,
+    Ready(bool), // Heartbeat in the lobby
+    Heartbeat,
+    Goodbye,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum HostMessage {
+    Start(MatchSettings, GameConfig),
+    // Lets a spectator join a match already under way
+    Spectate { match_id: u64, settings: MatchSettings, config: GameConfig, snapshot: Box<WorldSnapshot> },
+    State(NetState),
+    Ready(bool), // Heartbeat in the lobby
+    Heartbeat,
+    Goodbye,
+}
+
+// Everything the client needs to draw a tick
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NetState {
+    pub tick: u64,
+    pub input_ack: u64, // Last client input applied
+    pub paused: bool,
+    pub puck: [f32; 2],
+    pub paddles: [[f32; 2]; 2], // Left then right
+    pub score: (u32, u32),
+    pub serve_elapsed: f32,
+    pub clock_remaining: Option<f32>,
+    pub overtime: bool,
+    pub match_time: f32,
+}
+
+impl NetState {
+    pub fn capture(world: &mut World, input_ack: u64) -> Option<Self> {
+        let snapshot = snapshot::capture(world)?;
+        let paddle = |side| {
+            snapshot.paddles.iter().find(|paddle| paddle.side == side).map_or([0.0; 2], |paddle| paddle.body.position)
+        };
+        Some(Self {
+            tick: snapshot.tick,
+            input_ack,
+            paused: world.resource::<GamePaused>().0,
+            puck: snapshot.puck.position,
+            paddles: [paddle(Side::Left), paddle(Side::Right)],
+            score: snapshot.score,
+            serve_elapsed: snapshot.serve_delay.elapsed.as_secs_f32(),
+            clock_remaining: snapshot.clock_remaining,
+            overtime: snapshot.overtime,
+            match_time: snapshot.match_time,
+        })
+    }
+}
+
+// Packets are RON text, easy to read in a packet capture
+pub fn encode(message: &impl Serialize) -> Option<Vec<u8>> {
+    match ron::to_string(message) {
+        Ok(packet) => Some(packet.into_bytes()),
+        Err(error) => {
+            error!("Couldn't encode packet: {error}");
+            None
+        }
+    }
+}
+
+pub fn decode<M: DeserializeOwned>(packet: &[u8]) -> Option<M> {
+    std::str::from_utf8(packet).ok().and_then(|text| ron::from_str(text).ok())
+}
+
+#[derive(Resource)]
+pub struct NetSession {
+    socket: UdpSocket,
+    pub peer: Option<SocketAddr>,
+    pub local_side: Side,
+    pub connected: bool, // Heard from the peer at least once
+    pub lost: bool,
+    pub closed: bool, // The peer left
+    pub spectator: bool, // Watching someone else's match
+    last_received: Duration,
+    last_sent: Duration,
+    // Simulated network conditions for testing, see --net-latency and --net-loss
+    latency: Duration,
+    loss: f32,
+    delayed: VecDeque<(Duration, Vec<u8>)>, // Packets held back until their send time
+}
+
+impl NetSession {
+    pub fn bind(
+        address: impl ToSocketAddrs,
+        peer: Option<SocketAddr>,
+        local_side: Side,
+        options: &LaunchOptions,
+    ) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            peer,
+            local_side,
+            connected: false,
+            lost: false,
+            closed: false,
+            spectator: false,
+            last_received: Duration::ZERO,
+            last_sent: Duration::ZERO,
+            latency: options.net_latency,
+            loss: options.net_loss,
+            delayed: VecDeque::new(),
+        })
+    }
+
+    pub fn accept_peer(&mut self, peer: SocketAddr, now: Duration) {
+        info!("{peer} joined");
+        self.peer = Some(peer);
+        self.connected = true;
+        self.last_received = now;
+    }
+
+    pub fn send(&mut self, message: &impl Serialize, now: Duration) {
+        let Some(peer) = self.peer else {
+            return;
+        };
+        let Some(packet) = encode(message) else {
+            return;
+        };
+        self.last_sent = now;
+        if self.loss > 0.0 && rand::random::<f32>() < self.loss {
+            return;
+        }
+        self.delayed.push_back((now + self.latency, packet));
+        self.flush(peer, now);
+    }
+
+    fn flush(&mut self, peer: SocketAddr, now: Duration) {
+        while let Some((_, packet)) = self.delayed.front().filter(|(send_at, _)| *send_at <= now) {
+            // Dropped packets are fine, the next one carries the same information
+            if let Err(error) = self.socket.send_to(packet, peer)
+                && error.kind() != ErrorKind::WouldBlock
+            {
+                debug!("Couldn't send packet: {error}");
+            }
+            self.delayed.pop_front();
+        }
+    }
+
+    // Everything waiting on the socket. Until there's a peer, anyone may say hello.
+    pub fn receive<M: DeserializeOwned>(&mut self, now: Duration) -> Vec<(SocketAddr, M)> {
+        if let Some(peer) = self.peer {
+            self.flush(peer, now);
+        }
+        let mut messages = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let (length, from) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                // Windows reports an unreachable peer here, which the timeout already covers
+                Err(_) => continue,
+            };
+            if self.peer.is_some_and(|peer| peer != from) {
+                continue;
+            }
+            let Some(message) = decode(&buffer[..length]) else {
+                continue;
+            };
+            if self.peer.is_some() {
+                self.connected = true;
+                self.last_received = now;
+            }
+            messages.push((from, message));
+        }
+        messages
+    }
+
+    // Back to waiting for anyone, when a player leaves the lobby
+    pub fn drop_peer(&mut self) {
+        if let Some(peer) = self.peer.take() {
+            info!("{peer} left");
+        }
+        self.connected = false;
+        self.lost = false;
+        self.closed = false;
+        self.delayed.clear();
+    }
+
+    pub fn heartbeat_due(&self, now: Duration) -> bool {
+        now.saturating_sub(self.last_sent) >= HEARTBEAT_INTERVAL
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct NetHost {
+    inputs: VecDeque<(u64, Vec2)>,
+    queued_seq: u64,
+    applied_seq: u64,
+    input: Vec2,
+}
+
+#[derive(Resource)]
+pub struct NetClient {
+    next_seq: u64,
+    pending: VecDeque<(u64, Vec2)>, // Inputs the host may not have applied yet
+    states: VecDeque<NetState>, // In tick order
+    render_tick: u64,
+    host_paused: bool,
+    pending_pause: Option<bool>, // Asked the host for, not confirmed yet
+    saved_settings: MatchSettings, // Put back when the session ends
+    match_id: Option<u64>, // The match being spectated
+    pub join_snapshot: Option<WorldSnapshot>, // Where a spectator joins the match
+}
+
+impl NetClient {
+    pub fn follow_from(&mut self, tick: u64) {
+        self.states.clear();
+        self.render_tick = tick;
+    }
+
+    pub fn new(saved_settings: MatchSettings) -> Self {
+        Self {
+            next_seq: 1,
+            pending: VecDeque::new(),
+            states: VecDeque::new(),
+            render_tick: 0,
+            host_paused: false,
+            pending_pause: None,
+            saved_settings,
+            match_id: None,
+            join_snapshot: None,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn handle_net_buttons(
+    mut host_query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<HostGameButton>)>,
+    mut join_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<JoinGameButton>, Without<HostGameButton>),
+    >,
+    mut netcode_query: Query<
+        (&Interaction, &mut BackgroundColor, &Children),
+        (Changed<Interaction>, With<NetcodeButton>, Without<HostGameButton>, Without<JoinGameButton>),
+    >,
+    mut text_query: Query<&mut Text>,
+    mut commands: Commands,
+    mut options: ResMut<LaunchOptions>,
+    mut settings: ResMut<MatchSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, mut color, children) in &mut netcode_query {
+        match *interaction {
+            Interaction::Pressed => {
+                options.rollback = !options.rollback;
+                let mut texts = text_query.iter_many_mut(children);
+                while let Some(mut text) = texts.fetch_next() {
+                    *text = Text::new(netcode_label(options.rollback));
+                }
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+
+    for (interaction, mut color) in &mut host_query {
+        match *interaction {
+            Interaction::Pressed => {
+                if host_session(&mut commands, &options, &mut settings) {
+                    next_state.set(GameState::Connecting);
+                }
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+
+    for (interaction, mut color) in &mut join_query {
+        match *interaction {
+            Interaction::Pressed => {
+                let address = options.join_address();
+                let Some(host) = address.to_socket_addrs().ok().and_then(|mut addresses| addresses.next()) else {
+                    error!("Couldn't find host {address}");
+                    continue;
+                };
+                if join_session(&mut commands, &options, &settings, host, options.rollback) {
+                    next_state.set(GameState::Connecting);
+                }
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+}
+
+// Opens the host's side of a networked match, waiting on --port for a player
+pub fn host_session(commands: &mut Commands, options: &LaunchOptions, settings: &mut MatchSettings) -> bool {
+    let port = options.port.unwrap_or(DEFAULT_PORT);
+    match NetSession::bind(("0.0.0.0", port), None, Side::Left, options) {
+        Ok(session) => {
+            commands.insert_resource(session);
+            if options.rollback {
+                commands.insert_resource(RollbackSession::new(rand::random(), settings.clone()));
+            } else {
+                commands.insert_resource(NetHost::default());
+            }
+            settings.cpu_side = None;
+            true
+        }
+        Err(error) => {
+            error!("Couldn't host on port {port}: {error}");
+            false
+        }
+    }
+}
+
+pub fn join_session(
+    commands: &mut Commands,
+    options: &LaunchOptions,
+    settings: &MatchSettings,
+    host: SocketAddr,
+    rollback: bool,
+) -> bool {
+    let local = if host.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    match NetSession::bind(local, Some(host), Side::Right, options) {
+        Ok(session) => {
+            commands.insert_resource(session);
+            if rollback {
+                commands.insert_resource(RollbackSession::new(0, settings.clone()));
+            } else {
+                commands.insert_resource(NetClient::new(settings.clone()));
+            }
+            true
+        }
+        Err(error) => {
+            error!("Couldn't open a socket: {error}");
+            false
+        }
+    }
+}
+
+pub fn netcode_label(rollback: bool) -> &'static str {
+    if rollback { "NETCODE: ROLLBACK" } else { "NETCODE: HOST" }
+}
+
+// Connecting Systems
+pub fn setup_connecting_screen(
+    mut commands: Commands,
+    session: Res<NetSession>,
+    options: Res<LaunchOptions>,
+    bindings: Res<KeyBindings>,
+) {
+    let status = match session.peer {
+        None => format!(
+            "WAITING FOR OPPONENT\nPort {}\n\nYou are RED: {}",
+            options.port.unwrap_or(DEFAULT_PORT),
+            bindings.movement_label(Side::Left),
+        ),
+        Some(host) if session.spectator => format!("WAITING FOR A MATCH AT {host}"),
+        Some(host) => format!("CONNECTING TO {host}\n\nYou are BLUE: {}", bindings.movement_label(Side::Right)),
+    };
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ConnectingUI,
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::new(status),
+            TextFont {
+                font_size: 36.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            TextLayout::new_with_justify(Justify::Center),
+        ));
+        parent.spawn((
+            Text::new(format!("{}: Cancel", key_label(bindings.key(Action::Pause)))),
+            TextFont {
+                font_size: 24.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            Node {
+                margin: UiRect::top(Val::Px(30.0)),
+                ..default()
+            },
+        ));
+    });
+}
+
+pub fn handle_connecting_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if bindings.just_pressed(&keyboard_input, Action::Pause) {
+        next_state.set(GameState::StartScreen);
+    }
+}
+
+pub fn cleanup_connecting_screen(mut commands: Commands, query: Query<Entity, With<ConnectingUI>>) {
+    for entity in &query {
+        commands.entity(entity).despawn();
+    }
+}
+
+// Back at the menu: tell the peer, and put back the settings and config a
+// client played with
+#[allow(clippy::too_many_arguments)]
+pub fn end_net_session(
+    mut commands: Commands,
+    session: Option<ResMut<NetSession>>,
+    client: Option<Res<NetClient>>,
+    rollback: Option<Res<RollbackSession>>,
+    mut settings: ResMut<MatchSettings>,
+    mut config: ResMut<GameConfig>,
+    config_file: Res<ConfigFile>,
+    real_time: Res<Time<Real>>,
+) {
+    let Some(mut session) = session else {
+        return;
+    };
+    let now = real_time.elapsed();
+    if let Some(client) = client {
+        session.send(&ClientMessage::Goodbye, now);
+        *settings = client.saved_settings.clone();
+        config.set_if_neq(config_file.loaded.clone());
+    } else if let Some(rollback) = rollback {
+        session.send(&PeerMessage::Goodbye, now);
+        *settings = rollback.saved_settings.clone();
+        config.set_if_neq(config_file.loaded.clone());
+    } else {
+        session.send(&HostMessage::Goodbye, now);
+    }
+    commands.remove_resource::<NetSession>();
+    commands.remove_resource::<NetHost>();
+    commands.remove_resource::<NetClient>();
+    commands.remove_resource::<RollbackSession>();
+}
+
+// Run condition: spectators can only watch
+pub fn spectating(session: Option<Res<NetSession>>) -> bool {
+    session.is_some_and(|session| session.spectator)
+}
+
+pub fn mark_remote_paddle(
+    mut commands: Commands,
+    session: Res<NetSession>,
+    query: Query<(Entity, &Paddle)>,
+) {
+    for (entity, paddle) in &query {
+        if session.spectator || paddle.side != session.local_side {
+            commands.entity(entity).insert(RemotePaddle);
+        }
+    }
+}
+
+// Host Systems
+
+// Runs before the fixed ticks, so inputs that arrived this frame are used by them
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn receive_as_host(
+    mut session: ResMut<NetSession>,
+    mut host: ResMut<NetHost>,
+    real_time: Res<Time<Real>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut paused: ResMut<GamePaused>,
+    mut commands: Commands,
+    game_entities: Query<Entity, Or<(With<GameUI>, With<GameOverUI>)>>,
+    mut score: ResMut<Score>,
+    mut puck_timer: ResMut<PuckDelayTimer>,
+    mut lobby: Option<ResMut<Lobby>>,
+) {
+    let now = real_time.elapsed();
+    for (from, message) in session.receive::<ClientMessage>(now) {
+        if session.peer.is_none() && !matches!(message, ClientMessage::Hello { .. }) {
+            continue;
+        }
+        match message {
+            ClientMessage::Hello { version } => {
+                if version != NET_VERSION {
+                    warn!("{from} is running a different version of the game, ignoring it");
+                } else if session.peer.is_none() {
+                    session.accept_peer(from, now);
+                    // From the lobby the match waits until both players are ready
+                    if *state.get() != GameState::Lobby {
+                        next_state.set(GameState::Countdown);
+                    }
+                }
+            }
+            ClientMessage::Input { first_seq, inputs } => {
+                for (seq, input) in (first_seq..).zip(inputs) {
+                    if seq > host.queued_seq {
+                        host.inputs.push_back((seq, Vec2::from(input)));
+                        host.queued_seq = seq;
+                    }
+                }
+                let excess = host.inputs.len().saturating_sub(MAX_QUEUED_INPUTS);
+                host.inputs.drain(..excess);
+            }
+            ClientMessage::Pause(pause) => {
+                if *state.get() == GameState::Playing && paused.0 != pause {
+                    paused.0 = pause;
+                }
+            }
+            ClientMessage::Rematch => {
+                if *state.get() == GameState::GameOver {
+                    reset_match(&mut commands, game_entities.iter(), &mut score, &mut puck_timer);
+                    next_state.set(GameState::Countdown);
+                }
+            }
+            ClientMessage::Ready(ready) => {
+                if let Some(lobby) = lobby.as_mut() {
+                    lobby.peer_ready = ready;
+                }
+            }
+            ClientMessage::Heartbeat | ClientMessage::Watch { .. } => {}
+            ClientMessage::Goodbye => match lobby.as_mut() {
+                Some(lobby) => {
+                    lobby.peer_ready = false;
+                    session.drop_peer();
+                }
+                None => session.closed = true,
+            },
+        }
+    }
+}
+
+// One queued input per tick. When none has arrived the last one is held.
+pub fn apply_remote_input(
+    mut host: ResMut<NetHost>,
+    mut query: Query<&mut PaddleInput, With<RemotePaddle>>,
+) {
+    if let Some((seq, input)) = host.inputs.pop_front() {
+        host.applied_seq = seq;
+        host.input = input;
+    }
+    for mut paddle_input in &mut query {
+        paddle_input.0 = host.input;
+    }
+}
+
+pub fn send_as_host(world: &mut World) {
+    let now = world.resource::<Time<Real>>().elapsed();
+    let heartbeat_due = world.resource::<NetSession>().heartbeat_due(now);
+    let message = match world.resource::<State<GameState>>().get() {
+        // Repeated until the client starts, in case one goes missing
+        GameState::Countdown if heartbeat_due => {
+            Some(HostMessage::Start(world.resource::<MatchSettings>().clone(), world.resource::<GameConfig>().clone()))
+        }
+        GameState::Playing => {
+            let input_ack = world.resource::<NetHost>().applied_seq;
+            NetState::capture(world, input_ack).map(HostMessage::State)
+        }
+        GameState::Lobby if heartbeat_due => world.get_resource::<Lobby>().map(|lobby| HostMessage::Ready(lobby.ready)),
+        _ if heartbeat_due => Some(HostMessage::Heartbeat),
+        _ => None,
+    };
+    if let Some(message) = message {
+        world.resource_mut::<NetSession>().send(&message, now);
+    }
+}
+
+// Client Systems
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn receive_as_client(
+    mut session: ResMut<NetSession>,
+    mut client: ResMut<NetClient>,
+    real_time: Res<Time<Real>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut settings: ResMut<MatchSettings>,
+    mut config: ResMut<GameConfig>,
+    mut paused: ResMut<GamePaused>,
+    mut commands: Commands,
+    game_entities: Query<Entity, Or<(With<GameUI>, With<GameOverUI>)>>,
+    mut score: ResMut<Score>,
+    mut puck_timer: ResMut<PuckDelayTimer>,
+    mut lobby: Option<ResMut<Lobby>>,
+) {
+    for (_, message) in session.receive::<HostMessage>(real_time.elapsed()) {
+        match message {
+            HostMessage::Spectate { match_id, settings: match_settings, config: match_config, snapshot } => {
+                if client.match_id == Some(match_id) {
+                    continue;
+                }
+                match state.get() {
+                    GameState::Connecting | GameState::GameOver => {
+                        if *state.get() == GameState::GameOver {
+                            reset_match(&mut commands, game_entities.iter(), &mut score, &mut puck_timer);
+                        }
+                        *settings = match_settings;
+                        config.set_if_neq(match_config);
+                        client.match_id = Some(match_id);
+                        client.join_snapshot = Some(*snapshot);
+                        next_state.set(GameState::Playing);
+                    }
+                    // The match being watched was abandoned for a new one. Wait
+                    // for the next offer to join it.
+                    GameState::Playing => {
+                        reset_match(&mut commands, game_entities.iter(), &mut score, &mut puck_timer);
+                        client.match_id = None;
+                        next_state.set(GameState::Connecting);
+                    }
+                    _ => {}
+                }
+            }
+            HostMessage::Start(host_settings, host_config) => match state.get() {
+                GameState::Connecting | GameState::Lobby | GameState::GameOver => {
+                    if *state.get() == GameState::GameOver {
+                        reset_match(&mut commands, game_entities.iter(), &mut score, &mut puck_timer);
+                    }
+                    *settings = MatchSettings {
+                        cpu_side: None,
+                        ..host_settings
+                    };
+                    config.set_if_neq(host_config);
+                    next_state.set(GameState::Countdown);
+                }
+                _ => {}
+            },
+            HostMessage::State(net_state) => {
+                if !matches!(state.get(), GameState::Countdown | GameState::Playing) {
+                    continue;
+                }
+
+                // The host decides when the match is paused. A pause asked for
+                // here holds until the host has taken it.
+                client.host_paused = net_state.paused;
+                if client.pending_pause == Some(net_state.paused) {
+                    client.pending_pause = None;
+                }
+                if client.pending_pause.is_none() && paused.0 != net_state.paused {
+                    paused.0 = net_state.paused;
+                }
+
+                if client.states.back().is_none_or(|latest| net_state.tick > latest.tick) {
+                    client.states.push_back(net_state);
+                    if client.states.len() > MAX_STATES {
+                        client.states.pop_front();
+                    }
+                }
+            }
+            HostMessage::Ready(ready) => {
+                if let Some(lobby) = lobby.as_mut() {
+                    lobby.peer_ready = ready;
+                }
+            }
+            HostMessage::Heartbeat => {}
+            HostMessage::Goodbye => session.closed = true,
+        }
+    }
+}
+
+// A new match starts from tick zero, so nothing from the last one carries over
+pub fn reset_client_states(mut client: ResMut<NetClient>) {
+    client.follow_from(0);
+}
+
+pub fn send_client_input(
+    mut session: ResMut<NetSession>,
+    mut client: ResMut<NetClient>,
+    query: Query<&PaddleInput, (With<Paddle>, Without<RemotePaddle>)>,
+    real_time: Res<Time<Real>>,
+) {
+    let Ok(input) = query.single() else {
+        return;
+    };
+    let seq = client.next_seq;
+    client.next_seq += 1;
+    client.pending.push_back((seq, input.0));
+    if client.pending.len() > MAX_PENDING_INPUTS {
+        client.pending.pop_front();
+    }
+
+    let recent = client.pending.len().saturating_sub(INPUTS_PER_PACKET);
+    let first_seq = client.pending[recent].0;
+    let inputs = client.pending.iter().skip(recent).map(|(_, input)| (*input).into()).collect();
+    session.send(&ClientMessage::Input { first_seq, inputs }, real_time.elapsed());
+}
+
+// Heartbeats while there's nothing else to send, a hello until the host answers,
+// whether the player is ready in the lobby, and any pause waiting on the host
+pub fn send_as_client(
+    mut session: ResMut<NetSession>,
+    mut client: ResMut<NetClient>,
+    paused: Res<GamePaused>,
+    state: Res<State<GameState>>,
+    real_time: Res<Time<Real>>,
+    lobby: Option<Res<Lobby>>,
+) {
+    let now = real_time.elapsed();
+    if *state.get() == GameState::Playing && paused.is_changed() && paused.0 != client.host_paused {
+        client.pending_pause = Some(paused.0);
+    }
+
+    if let Some(pause) = client.pending_pause {
+        session.send(&ClientMessage::Pause(pause), now);
+    } else if session.heartbeat_due(now) {
+        let message = match state.get() {
+            _ if session.spectator => ClientMessage::Watch {
+                version: NET_VERSION,
+                match_id: client.match_id,
+            },
+            GameState::Connecting => ClientMessage::Hello { version: NET_VERSION },
+            GameState::Lobby if !session.connected => ClientMessage::Hello { version: NET_VERSION },
+            GameState::Lobby => ClientMessage::Ready(lobby.is_some_and(|lobby| lobby.ready)),
+            _ => ClientMessage::Heartbeat,
+        };
+        session.send(&message, now);
+    }
+}
+
+pub fn request_rematch(
+    mut session: ResMut<NetSession>,
+    query: Query<&Interaction, (Changed<Interaction>, With<RematchButton>)>,
+    real_time: Res<Time<Real>>,
+) {
+    if query.iter().any(|interaction| *interaction == Interaction::Pressed) {
+        session.send(&ClientMessage::Rematch, real_time.elapsed());
+    }
+}
+
+// The client's own paddle is put where the host last had it, then moved on by
+// every input the host hasn't applied yet. Everything else is drawn between the
+// two host states either side of a tick a little behind the latest.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_host_state(
+    mut client: ResMut<NetClient>,
+    settings: Res<MatchSettings>,
+    config: Res<GameConfig>,
+    time: Res<Time>,
+    mut puck_query: Query<&mut Position, With<Puck>>,
+    mut paddle_query: Query<(&mut Position, &Paddle, Has<RemotePaddle>), Without<Puck>>,
+    mut score: ResMut<Score>,
+    mut timer: ResMut<PuckDelayTimer>,
+    mut clock: ResMut<MatchClock>,
+    mut match_time: ResMut<MatchTime>,
+    mut goals: MessageWriter<GoalScored>,
+) {
+    let Some(latest) = client.states.back().cloned() else {
+        return;
+    };
+
+    client.pending.retain(|(seq, _)| *seq > latest.input_ack);
+    for (mut position, paddle, remote) in &mut paddle_query {
+        if remote {
+            continue;
+        }
+        let mut predicted = Vec2::from(latest.paddles[paddle.side as usize]);
+        for (_, input) in &client.pending {
+            predicted = step_paddle(paddle.side, predicted, *input, time.delta_secs(), &config);
+        }
+        position.0 = predicted;
+    }
+
+    // Stay a little behind the host, jumping back in if it falls too far behind.
+    // Catching right up is fine, that's how the last state of a match gets drawn.
+    let delay = ((INTERPOLATION_DELAY_SECONDS * settings.tick_rate) as u64).max(1);
+    client.render_tick = (client.render_tick + 1).min(latest.tick);
+    if client.render_tick + 2 * delay < latest.tick {
+        client.render_tick = latest.tick - delay;
+    }
+    let render_tick = client.render_tick;
+    let Some(from) = client.states.iter().rev().find(|state| state.tick <= render_tick).cloned() else {
+        return;
+    };
+    let to = client.states.iter().find(|state| state.tick >= render_tick).unwrap_or(&from);
+    // A goal resets everything, so there's nothing to blend across one
+    let alpha = if to.tick > from.tick && to.score == from.score {
+        (render_tick - from.tick) as f32 / (to.tick - from.tick) as f32
+    } else {
+        0.0
+    };
+    let blend = |from: [f32; 2], to: [f32; 2]| Vec2::from(from).lerp(Vec2::from(to), alpha);
+
+    for mut position in &mut puck_query {
+        position.0 = blend(from.puck, to.puck);
+    }
+    for (mut position, paddle, remote) in &mut paddle_query {
+        if remote {
+            let index = paddle.side as usize;
+            position.0 = blend(from.paddles[index], to.paddles[index]);
+        }
+    }
+
+    // Goals are announced here too, which gives the client its instant replay
+    if from.score.0 > score.left {
+        goals.write(GoalScored { side: Side::Left });
+    }
+    if from.score.1 > score.right {
+        goals.write(GoalScored { side: Side::Right });
+    }
+    score.left = from.score.0;
+    score.right = from.score.1;
+    timer.0.reset();
+    timer.0.tick(Duration::from_secs_f32(from.serve_elapsed));
+    clock.remaining = from.clock_remaining;
+    clock.overtime = from.overtime;
+    match_time.0 = from.match_time;
+}
+
+// Connection Systems
+
+pub fn check_connection(mut session: ResMut<NetSession>, real_time: Res<Time<Real>>) {
+    let silent = real_time.elapsed().saturating_sub(session.last_received) > TIMEOUT;
+    let lost = session.connected && (session.closed || silent);
+    if session.lost != lost {
+        session.lost = lost;
+        if lost {
+            warn!("Connection lost");
+        } else {
+            info!("Connection back");
+        }
+    }
+}
+
+pub fn update_connection_lost_display(
+    mut commands: Commands,
+    session: Res<NetSession>,
+    overlay_query: Query<Entity, With<ConnectionLostUI>>,
+) {
+    if session.lost && overlay_query.is_empty() {
+        commands.spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            GlobalZIndex(10),
+            ConnectionLostUI,
+        )).with_children(|parent| {
+            parent.spawn((
+                Text::new("CONNECTION LOST"),
+                TextFont {
+                    font_size: 64.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                Node {
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                },
+            ));
+            spawn_option_button(parent, "MAIN MENU", Color::srgb(0.8, 0.2, 0.2), LeaveMatchButton);
+        });
+    } else if !session.lost {
+        for entity in &overlay_query {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn handle_leave_match_input(
+    query: Query<&Interaction, (Changed<Interaction>, With<LeaveMatchButton>)>,
+    mut commands: Commands,
+    entities: Query<
+        Entity,
+        Or<(With<GameUI>, With<GameOverUI>, With<PauseScreenUI>, With<CountdownText>, With<ConnectionLostUI>)>,
+    >,
+    mut score: ResMut<Score>,
+    mut puck_timer: ResMut<PuckDelayTimer>,
+    mut paused: ResMut<GamePaused>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if query.iter().any(|interaction| *interaction == Interaction::Pressed) {
+        reset_match(&mut commands, entities.iter(), &mut score, &mut puck_timer);
+        paused.0 = false;
+        next_state.set(GameState::StartScreen);
+    }
+}
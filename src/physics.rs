@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use crate::config::GameConfig;
+use crate::resources::PhysicsMode;
+
+// Most bounces followed within a single step, so a puck wedged in a corner can't
+// loop forever
+const MAX_BOUNCES_PER_STEP: usize = 8;
+
+// Time of impact for two circles. start_offset is the first circle's center
+// relative to the second, relative_motion how far it moves relative to the second
+// over the step. Returns the fraction of the step (0 to 1) at which the centers
+// are first contact_distance apart, or None if they don't touch or are already
+// overlapping at the start.
+pub fn sweep_circles(start_offset: Vec2, relative_motion: Vec2, contact_distance: f32) -> Option<f32> {
+    let a = relative_motion.length_squared();
+    let b = 2.0 * start_offset.dot(relative_motion);
+    let c = start_offset.length_squared() - contact_distance * contact_distance;
+    if a == 0.0 || c < 0.0 || b >= 0.0 {
+        return None;
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / (2.0 * a);
+    (0.0..=1.0).contains(&t).then_some(t)
+}
+
+// Bounces velocity off a surface with the given normal, keeping restitution of
+// the speed along the normal. Velocities already leaving the surface are unchanged.
+pub fn reflect(velocity: Vec2, normal: Vec2, restitution: f32) -> Vec2 {
+    let velocity_along_normal = velocity.dot(normal);
+    if velocity_along_normal < 0.0 {
+        velocity - (1.0 + restitution) * velocity_along_normal * normal
+    } else {
+        velocity
+    }
+}
+
+// Puck velocity after striking a paddle with the given normal (paddle center to
+// puck center). The paddle acts as if it had infinite mass, so all of its velocity
+// along the normal goes into the puck.
+pub fn paddle_hit(puck_velocity: Vec2, paddle_velocity: Vec2, normal: Vec2, physics: PhysicsMode, config: &GameConfig) -> Vec2 {
+    let relative_velocity = reflect(puck_velocity - paddle_velocity, normal, config.paddle_restitution);
+    let velocity = (relative_velocity + paddle_velocity).clamp_length_max(config.puck_max_speed);
+
+    // Classic mode still lets a swing fire the puck off faster, but never lets a
+    // hit slow it below puck_speed
+    match physics {
+        PhysicsMode::Classic => velocity.normalize_or_zero() * velocity.length().max(config.puck_speed),
+        PhysicsMode::Realistic => velocity,
+    }
+}
+
+// Centers of the four goal posts at the ends of the goal mouths
+pub fn goal_posts(config: &GameConfig) -> [Vec2; 4] {
+    let (x, y) = (config.rink_width / 2.0, config.goal_width / 2.0);
+    [Vec2::new(-x, -y), Vec2::new(-x, y), Vec2::new(x, -y), Vec2::new(x, y)]
+}
+
+// Earliest wall, end wall or goal post the puck reaches within delta seconds, as
+// the time of impact and the surface normal
+fn first_rink_contact(position: Vec2, velocity: Vec2, delta: f32, config: &GameConfig) -> Option<(f32, Vec2)> {
+    let boundary = config.rink_height / 2.0 - config.puck_size / 2.0;
+    let end_boundary = config.rink_width / 2.0 - config.puck_size / 2.0;
+    let mut contact: Option<(f32, Vec2)> = None;
+    let mut consider = |time: f32, normal: Vec2| {
+        if (0.0..=delta).contains(&time) && contact.is_none_or(|(first, _)| time < first) {
+            contact = Some((time, normal));
+        }
+    };
+
+    // Top and bottom walls
+    if velocity.y > 0.0 {
+        consider(((boundary - position.y) / velocity.y).max(0.0), Vec2::NEG_Y);
+    } else if velocity.y < 0.0 {
+        consider(((-boundary - position.y) / velocity.y).max(0.0), Vec2::Y);
+    }
+
+    // End walls, except where the puck would cross into a goal mouth
+    let end_wall = if velocity.x > 0.0 && position.x <= end_boundary {
+        Some((end_boundary, Vec2::NEG_X))
+    } else if velocity.x < 0.0 && position.x >= -end_boundary {
+        Some((-end_boundary, Vec2::X))
+    } else {
+        None
+    };
+    if let Some((wall_x, normal)) = end_wall {
+        let time = (wall_x - position.x) / velocity.x;
+        let y_at_wall = position.y + velocity.y * time;
+        if y_at_wall.abs() >= config.goal_width / 2.0 {
+            consider(time, normal);
+        }
+    }
+
+    // Goal posts
+    let contact_distance = config.puck_size / 2.0 + config.goal_post_radius;
+    for post in goal_posts(config) {
+        if let Some(fraction) = sweep_circles(position - post, velocity * delta, contact_distance) {
+            let time = fraction * delta;
+            let normal = (position + velocity * time - post).normalize_or_zero();
+            consider(time, normal);
+        }
+    }
+
+    contact
+}
+
+// Moves the puck for delta seconds, bouncing off the walls and goal posts at the
+// moment it reaches them rather than after it has passed through
+pub fn advance_puck(position: &mut Vec2, velocity: &mut Vec2, delta: f32, restitution: f32, config: &GameConfig) {
+    let mut remaining = delta;
+    for _ in 0..MAX_BOUNCES_PER_STEP {
+        let Some((time, normal)) = first_rink_contact(*position, *velocity, remaining, config) else {
+            break;
+        };
+        *position += *velocity * time;
+        *velocity = reflect(*velocity, normal, restitution);
+        remaining -= time;
+    }
+    *position += *velocity * remaining;
+
+    // Never leave the puck outside the top and bottom walls
+    let boundary = config.rink_height / 2.0 - config.puck_size / 2.0;
+    position.y = position.y.clamp(-boundary, boundary);
+}
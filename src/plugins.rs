@@ -0,0 +1,361 @@
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy::ui::UiSystems;
+use crate::ai::*;
+use crate::bindings::*;
+use crate::config::*;
+use crate::display::*;
+use crate::gamepad::*;
+use crate::goal_replay::*;
+use crate::lobby::*;
+use crate::mouse::*;
+use crate::net::*;
+use crate::replay::*;
+use crate::replay_viewer::*;
+use crate::resources::*;
+use crate::rollback::*;
+use crate::spectate::*;
+use crate::systems::*;
+
+// The whole game, for the binary or for any other Bevy app that wants a rink.
+// It needs the DefaultPlugins, or at least windowing, rendering, input, UI and
+// state, to be added first. A headless one only needs the MinimalPlugins, and
+// leaves out everything drawn along with all reading and writing of files, so
+// tests can run the simulation with their own clock and inputs.
+#[derive(Clone, Default)]
+pub struct AirHockeyPlugin {
+    pub settings: MatchSettings, // The rules and physics the start screen begins with
+    pub config: GameConfig,
+    pub options: LaunchOptions,
+    pub headless: bool,
+}
+
+impl Plugin for AirHockeyPlugin {
+    fn build(&self, app: &mut App) {
+        if self.headless {
+            if !app.is_plugin_added::<StatesPlugin>() {
+                app.add_plugins(StatesPlugin);
+            }
+            if !app.is_plugin_added::<InputPlugin>() {
+                app.add_plugins(InputPlugin);
+            }
+            app.insert_resource(KeyBindings::default());
+        } else {
+            app.insert_resource(KeyBindings::load_or_default())
+                .add_plugins((DisplayPlugin, ReplayPlugin))
+                // Networked matches, replays and matches being recorded keep the config they started with
+                .add_systems(Update, reload_config
+                    .before(apply_config)
+                    .run_if(not(resource_exists::<NetSession>)
+                        .and(not(resource_exists::<ReplayPlayback>))
+                        .and(not(in_state(GameState::Playing).and(resource_exists::<ReplayRecorder>)))));
+        }
+
+        app.init_state::<GameState>()
+            .insert_resource(Score { left: 0, right: 0 })
+            .insert_resource(PuckDelayTimer(Timer::from_seconds(self.config.serve_delay, TimerMode::Once)))
+            .insert_resource(GamePaused(false))
+            .init_resource::<MatchTime>()
+            .init_resource::<MatchClock>()
+            .insert_resource(self.settings.clone())
+            .insert_resource(self.options.clone())
+            .init_resource::<SimulationTick>()
+            .init_resource::<PuckTimeUsed>()
+            .add_message::<GoalScored>()
+            .init_resource::<GameRng>()
+            .insert_resource(ConfigFile::new(self.config.clone()))
+            .insert_resource(self.config.clone())
+            .add_plugins((
+                MenuPlugin,
+                CountdownPlugin,
+                GameplayPlugin,
+                HudPlugin,
+                PausePlugin,
+                NetPlugin,
+            ));
+    }
+}
+
+// The camera, window settings, letterboxing, and the images and meshes the rink
+// is drawn with
+pub struct DisplayPlugin;
+
+impl Plugin for DisplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DisplaySettings>()
+            .add_systems(Startup, (setup_camera, spawn_letterbox))
+            .add_systems(Update, (
+                // The window starts out however the app made it
+                apply_display_settings.run_if(resource_changed::<DisplaySettings>.and(not(resource_added::<DisplaySettings>))),
+                fit_camera_to_rink.run_if(resource_changed::<GameConfig>),
+                scale_ui,
+                add_visuals,
+                resize_meshes.run_if(resource_changed::<GameConfig>),
+            ));
+    }
+}
+
+// The start screen, the settings, controls and controllers screens, and the
+// results screen
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GamepadAssignments>()
+            .init_resource::<MenuFocus>()
+            .init_resource::<MouseControl>()
+            .add_systems(PreUpdate, (assign_gamepads, navigate_menus.after(UiSystems::Focus)))
+            .add_systems(OnEnter(GameState::StartScreen), (
+                stop_replay,
+                end_net_session,
+                setup_start_screen,
+                start_launch_replay,
+                start_launch_spectate,
+            ).chain())
+            .add_systems(Update, (
+                handle_start_screen_input,
+                handle_settings_button,
+                handle_mouse_button,
+            ).run_if(in_state(GameState::StartScreen)))
+            .add_systems(OnEnter(GameState::Settings), (
+                cleanup_start_screen,
+                setup_settings_screen,
+            ))
+            .add_systems(Update, (
+                handle_settings_input,
+                handle_controls_button,
+                update_settings_display,
+            ).chain().run_if(in_state(GameState::Settings)))
+            .add_systems(OnExit(GameState::Settings), cleanup_settings_screen)
+            .add_systems(OnEnter(GameState::Controls), (
+                cleanup_start_screen,
+                setup_controls_screen,
+            ))
+            .add_systems(Update, (
+                handle_controls_input,
+                handle_controllers_button,
+                update_controls_display,
+            ).chain().run_if(in_state(GameState::Controls)))
+            .add_systems(OnExit(GameState::Controls), cleanup_controls_screen)
+            .add_systems(OnEnter(GameState::Controllers), (
+                cleanup_start_screen,
+                setup_controllers_screen,
+            ))
+            .add_systems(Update, (
+                handle_controllers_input,
+                update_controllers_display,
+            ).chain().run_if(in_state(GameState::Controllers)))
+            .add_systems(OnExit(GameState::Controllers), cleanup_controllers_screen)
+            .add_systems(OnEnter(GameState::GameOver), setup_game_over_screen)
+            .add_systems(Update, handle_game_over_input.run_if(in_state(GameState::GameOver)));
+    }
+}
+
+pub struct CountdownPlugin;
+
+impl Plugin for CountdownPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Countdown), (cleanup_start_screen, setup_countdown))
+            .add_systems(Update, update_countdown.run_if(in_state(GameState::Countdown)));
+    }
+}
+
+// The match itself: reading input and running the simulation at a fixed tick
+// rate, then drawing it between ticks
+pub struct GameplayPlugin;
+
+impl Plugin for GameplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MouseTarget>()
+            .add_systems(Update, apply_config.run_if(resource_changed::<GameConfig>))
+            .add_systems(OnEnter(GameState::Playing), (
+                cleanup_countdown,
+                setup_game,
+                start_recording.run_if(not(resource_exists::<NetClient>)),
+                reset_recent_frames,
+                mark_remote_paddle.run_if(resource_exists::<NetSession>),
+                mark_mouse_paddle,
+                join_spectated_match.run_if(spectating),
+                next_spectated_match.run_if(resource_exists::<SpectatorServer>.and(not(resource_exists::<NetClient>))),
+            ).chain())
+            .add_systems(OnExit(GameState::Playing), stop_goal_replay)
+            .add_systems(Update, skip_goal_replay.run_if(in_state(GameState::Playing).and(resource_exists::<GoalReplay>)))
+            .add_systems(RunFixedMainLoop, (
+                read_mouse_target
+                    .in_set(RunFixedMainLoopSystems::BeforeFixedMainLoop)
+                    .run_if(in_state(GameState::Playing)),
+                read_paddle_input
+                    .in_set(RunFixedMainLoopSystems::BeforeFixedMainLoop)
+                    .run_if(in_state(GameState::Playing).and(not(resource_exists::<ReplayPlayback>))),
+                // Frozen while paused or over, otherwise the last tick would keep flickering
+                interpolate_transforms
+                    .in_set(RunFixedMainLoopSystems::AfterFixedMainLoop)
+                    .run_if(simulation_running),
+                play_goal_replay
+                    .after(interpolate_transforms)
+                    .in_set(RunFixedMainLoopSystems::AfterFixedMainLoop)
+                    .run_if(simulation_running.and(resource_exists::<GoalReplay>)),
+            ))
+            .add_systems(FixedUpdate, (
+                record_snapshot,
+                save_rollback_state.run_if(resource_exists::<RollbackSession>),
+                store_previous_positions,
+                tick_serve_delay,
+                tick_match_time,
+                update_match_clock,
+                // Paddles move first, then the puck is swept against them and the walls
+                (
+                    drive_cpu_paddles,
+                    follow_mouse,
+                    apply_replay_inputs.run_if(resource_exists::<ReplayPlayback>),
+                    apply_remote_input.run_if(resource_exists::<NetHost>),
+                    apply_rollback_inputs.run_if(resource_exists::<RollbackSession>),
+                    record_inputs,
+                ).chain(),
+                move_paddles,
+                check_paddle_collision,
+                move_puck,
+                record_recent_frames.run_if(in_state(GameState::Playing)),
+                check_score,
+                start_goal_replay.run_if(in_state(GameState::Playing)),
+                record_score_events,
+                check_replay_sync.run_if(resource_exists::<ReplayPlayback>),
+                // Rollback matches end on confirmed ticks only, see end_rollback_match_when_won
+                end_match_when_won.run_if(in_state(GameState::Playing).and(not(resource_exists::<RollbackSession>))),
+                advance_tick,
+            ).chain().run_if(simulation_running.and(not(resource_exists::<NetClient>)).and(not(rollback_stalled))))
+            // A networked client only moves its own paddle, the host does the rest
+            .add_systems(FixedUpdate, (
+                store_previous_positions,
+                follow_mouse,
+                send_client_input,
+                move_paddles,
+                apply_host_state,
+                start_goal_replay,
+                record_recent_frames,
+                end_match_when_won,
+                advance_tick,
+            ).chain().run_if(simulation_running.and(resource_exists::<NetClient>)));
+    }
+}
+
+// The score, clock and replay progress shown over a match
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (
+            update_score_display,
+            update_clock_display,
+            update_replay_display,
+        ).run_if(in_state(GameState::Playing)));
+    }
+}
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (
+            handle_pause_input.run_if(not(spectating)),
+            handle_pause_screen_input,
+            update_pause_display,
+        ).run_if(in_state(GameState::Playing)));
+    }
+}
+
+// Saving every match and watching them back in the replay viewer
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::ReplayViewer), (
+            cleanup_start_screen,
+            setup_game,
+            start_recording,
+            setup_replay_viewer,
+        ).chain())
+            .add_systems(Update, (
+                handle_replay_viewer_input,
+                apply_replay_seek,
+                update_score_display,
+                update_clock_display,
+                update_replay_display,
+                update_replay_viewer_display,
+            ).chain().run_if(in_state(GameState::ReplayViewer)))
+            .add_systems(OnExit(GameState::ReplayViewer), cleanup_replay_viewer)
+            .add_systems(OnEnter(GameState::GameOver), save_replay.run_if(not(resource_exists::<NetClient>)));
+    }
+}
+
+// Networked matches, both host-authoritative and rollback, the lobby and spectators
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, start_spectator_server)
+            .add_systems(Update, (
+                handle_net_buttons,
+                handle_spectate_button,
+                handle_lobby_button,
+            ).run_if(in_state(GameState::StartScreen)))
+            .add_systems(OnEnter(GameState::Lobby), (
+                cleanup_start_screen,
+                setup_lobby,
+            ))
+            .add_systems(Update, (
+                handle_lobby_input,
+                handle_net_buttons,
+                run_discovery,
+                update_lobby_display,
+            ).chain().run_if(in_state(GameState::Lobby)))
+            .add_systems(OnExit(GameState::Lobby), cleanup_lobby)
+            .add_systems(OnEnter(GameState::Connecting), (
+                cleanup_start_screen,
+                setup_connecting_screen,
+            ))
+            .add_systems(Update, handle_connecting_input.run_if(in_state(GameState::Connecting)))
+            .add_systems(OnExit(GameState::Connecting), cleanup_connecting_screen)
+            .add_systems(OnEnter(GameState::Countdown), (
+                reset_client_states.run_if(resource_exists::<NetClient>),
+                start_rollback_match.run_if(resource_exists::<RollbackSession>),
+            ))
+            // Networking runs in every state while there's a session, so a dropped
+            // connection is noticed whatever the players are doing
+            .add_systems(Update, (
+                check_connection,
+                start_lobby_match.run_if(in_state(GameState::Lobby)),
+                update_connection_lost_display,
+                handle_leave_match_input,
+            ).chain().run_if(resource_exists::<NetSession>))
+            .add_systems(Update, request_rematch.run_if(in_state(GameState::GameOver).and(resource_exists::<NetClient>)))
+            .add_systems(RunFixedMainLoop, (
+                receive_as_host
+                    .in_set(RunFixedMainLoopSystems::BeforeFixedMainLoop)
+                    .run_if(resource_exists::<NetHost>),
+                receive_as_client
+                    .in_set(RunFixedMainLoopSystems::BeforeFixedMainLoop)
+                    .run_if(resource_exists::<NetClient>),
+                send_as_host
+                    .in_set(RunFixedMainLoopSystems::AfterFixedMainLoop)
+                    .run_if(resource_exists::<NetHost>),
+                send_as_client
+                    .in_set(RunFixedMainLoopSystems::AfterFixedMainLoop)
+                    .run_if(resource_exists::<NetClient>),
+                // Before the keyboard is read, so playing ticks again can't overwrite this frame's input
+                (receive_as_peer, roll_back.run_if(in_state(GameState::Playing)))
+                    .chain()
+                    .before(read_paddle_input)
+                    .in_set(RunFixedMainLoopSystems::BeforeFixedMainLoop)
+                    .run_if(resource_exists::<RollbackSession>),
+                (end_rollback_match_when_won.run_if(in_state(GameState::Playing)), send_as_peer)
+                    .chain()
+                    .in_set(RunFixedMainLoopSystems::AfterFixedMainLoop)
+                    .run_if(resource_exists::<RollbackSession>),
+                serve_spectators
+                    .in_set(RunFixedMainLoopSystems::AfterFixedMainLoop)
+                    .run_if(resource_exists::<SpectatorServer>),
+            ));
+    }
+}
@@ -0,0 +1,364 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::components::*;
+use crate::config::{ConfigFile, GameConfig};
+use crate::resources::*;
+use crate::snapshot::{self, WorldSnapshot};
+
+// Bumped whenever the file layout or anything affecting the simulation changes,
+// so an old replay is refused instead of silently playing out differently
+pub const REPLAY_VERSION: u32 = 2;
+pub const REPLAY_DIR: &str = "replays";
+// How often the full match state is saved alongside the inputs, so the replay
+// viewer can jump anywhere without re-simulating from the start
+pub const SNAPSHOT_INTERVAL_SECONDS: f64 = 5.0;
+
+// Both paddles' inputs, held for a number of ticks in a row. Keyboard input rarely
+// changes from one tick to the next so storing runs keeps files small.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct InputRun {
+    pub ticks: u32,
+    pub left: [f32; 2],
+    pub right: [f32; 2],
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ScoreEvent {
+    pub tick: u64,
+    pub side: Side,
+}
+
+// Everything needed to play a match again tick for tick
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReplayFile {
+    pub version: u32,
+    pub seed: u64,
+    pub settings: MatchSettings,
+    #[serde(default)] // Replays recorded before the config file played with the defaults
+    pub config: GameConfig,
+    pub inputs: Vec<InputRun>,
+    pub score_events: Vec<ScoreEvent>,
+    pub snapshots: Vec<WorldSnapshot>, // In tick order
+}
+
+impl ReplayFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+        let replay: ReplayFile = ron::from_str(&text).map_err(|e| format!("{} is not a replay: {e}", path.display()))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "{} is replay version {}, this game plays version {REPLAY_VERSION}",
+                path.display(),
+                replay.version,
+            ));
+        }
+        // Played back at the tick rate it was recorded at, which has to be one Bevy can run
+        check_tick_rate(replay.settings.tick_rate).map_err(|e| format!("{} has a tick_rate that {e}", path.display()))?;
+        Ok(replay)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("can't create {}: {e}", dir.display()))?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let text = ron::to_string(self).map_err(|e| format!("can't encode replay: {e}"))?;
+
+        // Matches that end in the same second get numbered rather than overwritten
+        let mut count = 1;
+        loop {
+            let name = match count {
+                1 => format!("match_{timestamp}.ron"),
+                _ => format!("match_{timestamp}_{count}.ron"),
+            };
+            let path = dir.join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(text.as_bytes()).map_err(|e| format!("can't write {}: {e}", path.display()))?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => count += 1,
+                Err(e) => return Err(format!("can't create {}: {e}", path.display())),
+            }
+        }
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.inputs.iter().map(|run| run.ticks as u64).sum()
+    }
+
+    // Inputs for a given tick, or None once the recording has run out
+    pub fn input_at(&self, tick: u64) -> Option<([f32; 2], [f32; 2])> {
+        let mut start = 0;
+        for run in &self.inputs {
+            if tick < start + run.ticks as u64 {
+                return Some((run.left, run.right));
+            }
+            start += run.ticks as u64;
+        }
+        None
+    }
+
+    // The latest snapshot at or before a tick
+    pub fn snapshot_before(&self, tick: u64) -> Option<&WorldSnapshot> {
+        self.snapshots.iter().rev().find(|snapshot| snapshot.tick <= tick)
+    }
+
+    pub fn snapshot_interval(&self) -> u64 {
+        ((self.settings.tick_rate * SNAPSHOT_INTERVAL_SECONDS) as u64).max(1)
+    }
+
+    // Forgets everything from a tick on, so it can be recorded again
+    pub fn rewind(&mut self, tick: u64) {
+        let mut start = 0;
+        let mut kept = 0;
+        for run in &mut self.inputs {
+            if start >= tick {
+                break;
+            }
+            run.ticks = run.ticks.min((tick - start) as u32);
+            start += run.ticks as u64;
+            kept += 1;
+        }
+        self.inputs.truncate(kept);
+        self.score_events.retain(|event| event.tick < tick);
+        self.snapshots.retain(|snapshot| snapshot.tick < tick);
+    }
+
+    pub fn push_input(&mut self, left: Vec2, right: Vec2) {
+        let (left, right) = (left.to_array(), right.to_array());
+        match self.inputs.last_mut() {
+            Some(run) if run.left == left && run.right == right => run.ticks += 1,
+            _ => self.inputs.push(InputRun { ticks: 1, left, right }),
+        }
+    }
+}
+
+// The match being recorded. Replaced at the start of every match.
+#[derive(Resource)]
+pub struct ReplayRecorder(pub ReplayFile);
+
+// Present while a recorded match is being played back instead of the keyboard
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: ReplayFile,
+    pub next_score_event: usize,
+    pub desync: Option<String>,
+    pub saved_settings: MatchSettings, // Restored once the replay is left, with the config file's settings
+}
+
+// The most recently saved replay, if there are any
+pub fn latest_replay() -> Option<PathBuf> {
+    fs::read_dir(REPLAY_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+        .max_by_key(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+}
+
+// Loads a replay and sets the match up to play it back
+pub fn start_replay(commands: &mut Commands, path: &Path, settings: &mut MatchSettings, config: &mut GameConfig) -> bool {
+    match ReplayFile::load(path) {
+        Ok(replay) => {
+            info!("Playing replay {}", path.display());
+            let saved_settings = settings.clone();
+            *settings = MatchSettings {
+                seed: Some(replay.seed),
+                ..replay.settings.clone()
+            };
+            *config = replay.config.clone();
+            commands.insert_resource(ReplayPlayback {
+                replay,
+                next_score_event: 0,
+                desync: None,
+                saved_settings,
+            });
+            true
+        }
+        Err(error) => {
+            error!("Couldn't start replay: {error}");
+            false
+        }
+    }
+}
+
+// Opens a replay given with --replay in the viewer as soon as the game starts
+pub fn start_launch_replay(
+    mut commands: Commands,
+    mut options: ResMut<LaunchOptions>,
+    mut settings: ResMut<MatchSettings>,
+    mut config: ResMut<GameConfig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if let Some(path) = options.replay.take()
+        && start_replay(&mut commands, &path, &mut settings, &mut config)
+    {
+        next_state.set(GameState::ReplayViewer);
+    }
+}
+
+// A fresh recording for every match, and a rewound one for every replay
+pub fn start_recording(
+    mut commands: Commands,
+    game_rng: Res<GameRng>,
+    settings: Res<MatchSettings>,
+    config: Res<GameConfig>,
+    playback: Option<ResMut<ReplayPlayback>>,
+) {
+    commands.insert_resource(ReplayRecorder(ReplayFile {
+        version: REPLAY_VERSION,
+        seed: game_rng.seed,
+        settings: settings.clone(),
+        config: config.clone(),
+        inputs: Vec::new(),
+        score_events: Vec::new(),
+        snapshots: Vec::new(),
+    }));
+
+    if let Some(mut playback) = playback {
+        playback.next_score_event = 0;
+        playback.desync = None;
+    }
+}
+
+// Stands in for the keyboard (and the CPU) during playback
+pub fn apply_replay_inputs(
+    playback: Res<ReplayPlayback>,
+    tick: Res<SimulationTick>,
+    mut query: Query<(&mut PaddleInput, &Paddle)>,
+) {
+    let (left, right) = playback.replay.input_at(tick.0).unwrap_or_default();
+    for (mut input, paddle) in &mut query {
+        input.0 = Vec2::from_array(match paddle.side {
+            Side::Left => left,
+            Side::Right => right,
+        });
+    }
+}
+
+// Runs just before move_paddles, so it sees exactly what the paddles will use
+pub fn record_inputs(
+    mut recorder: ResMut<ReplayRecorder>,
+    query: Query<(&PaddleInput, &Paddle)>,
+) {
+    let mut inputs = (Vec2::ZERO, Vec2::ZERO);
+    for (input, paddle) in &query {
+        match paddle.side {
+            Side::Left => inputs.0 = input.0,
+            Side::Right => inputs.1 = input.0,
+        }
+    }
+    recorder.0.push_input(inputs.0, inputs.1);
+}
+
+// First in every tick, so a snapshot holds the state the tick starts from
+pub fn record_snapshot(world: &mut World) {
+    let tick = world.resource::<SimulationTick>().0;
+    let Some(recorder) = world.get_resource::<ReplayRecorder>() else {
+        return;
+    };
+    let already_taken = recorder.0.snapshots.last().is_some_and(|snapshot| snapshot.tick == tick);
+    if !tick.is_multiple_of(recorder.0.snapshot_interval()) || already_taken {
+        return;
+    }
+
+    if let Some(snapshot) = snapshot::capture(world) {
+        world.resource_mut::<ReplayRecorder>().0.snapshots.push(snapshot);
+    }
+}
+
+pub fn record_score_events(
+    mut recorder: ResMut<ReplayRecorder>,
+    mut goals: MessageReader<GoalScored>,
+    tick: Res<SimulationTick>,
+) {
+    for goal in goals.read() {
+        recorder.0.score_events.push(ScoreEvent { tick: tick.0, side: goal.side });
+    }
+}
+
+// Compares goals in the playback against the ones recorded with the replay. Any
+// difference means the simulation no longer matches the one that was recorded.
+pub fn check_replay_sync(
+    mut playback: ResMut<ReplayPlayback>,
+    mut goals: MessageReader<GoalScored>,
+    tick: Res<SimulationTick>,
+) {
+    let playback = &mut *playback;
+    let mut desync = None;
+
+    for goal in goals.read() {
+        let actual = ScoreEvent { tick: tick.0, side: goal.side };
+        let expected = playback.replay.score_events.get(playback.next_score_event).copied();
+        playback.next_score_event += 1;
+        if expected != Some(actual) {
+            desync = Some(format!("goal at tick {} was recorded as {expected:?}", tick.0));
+        }
+    }
+
+    // A recorded goal that never happened
+    if let Some(expected) = playback.replay.score_events.get(playback.next_score_event)
+        && tick.0 > expected.tick
+    {
+        desync = Some(format!("missed the goal recorded at tick {}", expected.tick));
+    }
+
+    // Only the first difference is reported, everything after it follows on
+    if let Some(desync) = desync
+        && playback.desync.is_none()
+    {
+        error!("Replay desynced: {desync}");
+        playback.desync = Some(desync);
+    }
+}
+
+// Replays are written when a match is won. Watching one doesn't record it again.
+pub fn save_replay(recorder: Option<Res<ReplayRecorder>>, playback: Option<Res<ReplayPlayback>>) {
+    let Some(recorder) = recorder else {
+        return;
+    };
+    if playback.is_some() {
+        return;
+    }
+    match recorder.0.save(Path::new(REPLAY_DIR)) {
+        Ok(path) => info!("Saved replay to {}", path.display()),
+        Err(error) => error!("Couldn't save replay: {error}"),
+    }
+}
+
+// Back at the menu the player's own settings and config come back
+pub fn stop_replay(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    mut settings: ResMut<MatchSettings>,
+    mut config: ResMut<GameConfig>,
+    config_file: Res<ConfigFile>,
+) {
+    if let Some(playback) = playback {
+        *settings = playback.saved_settings.clone();
+        config.set_if_neq(config_file.loaded.clone());
+        commands.remove_resource::<ReplayPlayback>();
+    }
+}
+
+pub fn update_replay_display(
+    playback: Option<Res<ReplayPlayback>>,
+    tick: Res<SimulationTick>,
+    mut query: Query<&mut Text, With<ReplayText>>,
+) {
+    let label = match playback {
+        Some(playback) if playback.desync.is_some() => "REPLAY DESYNCED".to_string(),
+        Some(playback) => {
+            let progress = tick.0 as f32 / playback.replay.tick_count().max(1) as f32;
+            format!("REPLAY {:.0}%", progress.min(1.0) * 100.0)
+        }
+        None => String::new(),
+    };
+    for mut text in &mut query {
+        if text.0 != label {
+            *text = Text::new(label.clone());
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+use crate::bindings::{Action, KeyBindings, key_label};
+use crate::components::*;
+use crate::resources::*;
+use crate::replay::*;
+use crate::snapshot;
+use crate::systems::{format_clock, reset_match};
+
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const NORMAL_SPEED: usize = 2;
+const SKIP_SECONDS: f64 = 5.0; // Left/Right arrow jump
+const MARKER_LEAD_SECONDS: f64 = 2.0; // Goal markers jump to this long before the goal
+
+#[derive(Resource)]
+pub struct ReplayViewer {
+    pub speed_index: usize,
+    pub seek: Option<u64>, // Tick to jump to on the next frame
+}
+
+pub fn setup_replay_viewer(
+    mut commands: Commands,
+    playback: Res<ReplayPlayback>,
+    mut paused: ResMut<GamePaused>,
+) {
+    commands.insert_resource(ReplayViewer {
+        speed_index: NORMAL_SPEED,
+        seek: None,
+    });
+    paused.0 = false;
+
+    let total_ticks = playback.replay.tick_count().max(1);
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(0.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(6.0),
+            ..default()
+        },
+        ReplayViewerUI,
+    )).with_children(|parent| {
+        // Speed, position and controls
+        parent.spawn((
+            Text::new(""),
+            TextFont {
+                font_size: 18.0,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            ReplayStatusText,
+        ));
+
+        // Timeline, click anywhere on it to jump there
+        parent.spawn((
+            Button,
+            Node {
+                width: Val::Px(600.0),
+                height: Val::Px(16.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            RelativeCursorPosition::default(),
+            ReplayTimeline,
+        )).with_children(|parent| {
+            for event in &playback.replay.score_events {
+                let color = match event.side {
+                    Side::Left => Color::srgb(1.0, 0.0, 0.0),
+                    Side::Right => Color::srgb(0.0, 0.0, 1.0),
+                };
+                parent.spawn((
+                    Button,
+                    Node {
+                        position_type: PositionType::Absolute,
+                        left: Val::Percent(event.tick as f32 / total_ticks as f32 * 100.0),
+                        width: Val::Px(6.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(color),
+                    GoalMarker { tick: event.tick },
+                ));
+            }
+
+            parent.spawn((
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: Val::Percent(0.0),
+                    width: Val::Px(3.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(Color::WHITE),
+                ReplayPlayhead,
+            ));
+        });
+    });
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn handle_replay_viewer_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    timeline_query: Query<(&Interaction, &RelativeCursorPosition), (Changed<Interaction>, With<ReplayTimeline>)>,
+    marker_query: Query<(&Interaction, &GoalMarker), Changed<Interaction>>,
+    mut viewer: ResMut<ReplayViewer>,
+    mut paused: ResMut<GamePaused>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    playback: Res<ReplayPlayback>,
+    tick: Res<SimulationTick>,
+    mut next_state: ResMut<NextState<GameState>>,
+    bindings: Res<KeyBindings>,
+) {
+    let total_ticks = playback.replay.tick_count();
+    let tick_rate = playback.replay.settings.tick_rate;
+
+    if bindings.just_pressed(&keyboard_input, Action::Pause) {
+        next_state.set(GameState::StartScreen);
+        return;
+    }
+
+    // Play/pause, starting again from the top once the end has been reached
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        paused.0 = !paused.0;
+        if !paused.0 && tick.0 >= total_ticks {
+            viewer.seek = Some(0);
+        }
+    }
+
+    // Playback speed
+    if keyboard_input.just_pressed(KeyCode::BracketLeft) {
+        viewer.speed_index = viewer.speed_index.saturating_sub(1);
+    }
+    if keyboard_input.just_pressed(KeyCode::BracketRight) {
+        viewer.speed_index = (viewer.speed_index + 1).min(SPEEDS.len() - 1);
+    }
+    if viewer.is_changed() {
+        virtual_time.set_relative_speed(SPEEDS[viewer.speed_index]);
+    }
+
+    // Single ticks, which also pause playback
+    if keyboard_input.just_pressed(KeyCode::Period) {
+        paused.0 = true;
+        viewer.seek = Some(tick.0 + 1);
+    }
+    if keyboard_input.just_pressed(KeyCode::Comma) {
+        paused.0 = true;
+        viewer.seek = Some(tick.0.saturating_sub(1));
+    }
+
+    // Skipping
+    let skip = (SKIP_SECONDS * tick_rate) as u64;
+    if keyboard_input.just_pressed(KeyCode::ArrowLeft) {
+        viewer.seek = Some(tick.0.saturating_sub(skip));
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowRight) {
+        viewer.seek = Some(tick.0 + skip);
+    }
+
+    // Clicking the timeline or one of its goal markers
+    for (interaction, marker) in &marker_query {
+        if *interaction == Interaction::Pressed {
+            let lead = (MARKER_LEAD_SECONDS * tick_rate) as u64;
+            viewer.seek = Some(marker.tick.saturating_sub(lead));
+        }
+    }
+    for (interaction, cursor) in &timeline_query {
+        if *interaction == Interaction::Pressed
+            && let Some(normalized) = cursor.normalized
+        {
+            let fraction = (normalized.x + 0.5).clamp(0.0, 1.0) as f64;
+            viewer.seek = Some((fraction * total_ticks as f64) as u64);
+        }
+    }
+
+    // Hold on the last recorded tick
+    if tick.0 >= total_ticks && viewer.seek.is_none() {
+        paused.0 = true;
+    }
+}
+
+// Jumps to the requested tick by restoring the closest snapshot before it and
+// simulating forward from there. Going forward a short way just simulates on from
+// the current tick.
+pub fn apply_replay_seek(world: &mut World) {
+    let Some(target) = world.resource_mut::<ReplayViewer>().seek.take() else {
+        return;
+    };
+    let playback = world.resource::<ReplayPlayback>();
+    let target = target.min(playback.replay.tick_count());
+    let current = world.resource::<SimulationTick>().0;
+
+    let snapshot = playback.replay.snapshot_before(target).cloned();
+    let from_snapshot = match &snapshot {
+        Some(snapshot) => target < current || snapshot.tick > current,
+        None => false,
+    };
+    if let Some(snapshot) = snapshot.filter(|_| from_snapshot) {
+        snapshot::restore(world, &snapshot);
+
+        // Goals before the snapshot have already been checked
+        let mut playback = world.resource_mut::<ReplayPlayback>();
+        playback.next_score_event = playback
+            .replay
+            .score_events
+            .iter()
+            .filter(|event| event.tick < snapshot.tick)
+            .count();
+    } else if target < current {
+        warn!("Replay has no snapshot to rewind to tick {target}");
+        return;
+    }
+
+    while world.resource::<SimulationTick>().0 < target {
+        snapshot::run_simulation_tick(world);
+    }
+
+    // Draw exactly where the simulation is, there is nothing to interpolate toward
+    let mut bodies = world.query::<(&mut Transform, &Position, &mut PreviousPosition)>();
+    for (mut transform, position, mut previous) in bodies.iter_mut(world) {
+        previous.0 = position.0;
+        transform.translation = position.0.extend(transform.translation.z);
+    }
+}
+
+pub fn update_replay_viewer_display(
+    viewer: Res<ReplayViewer>,
+    playback: Res<ReplayPlayback>,
+    paused: Res<GamePaused>,
+    tick: Res<SimulationTick>,
+    mut playhead_query: Query<&mut Node, With<ReplayPlayhead>>,
+    mut status_query: Query<&mut Text, With<ReplayStatusText>>,
+    bindings: Res<KeyBindings>,
+) {
+    let total_ticks = playback.replay.tick_count().max(1);
+    let tick_rate = playback.replay.settings.tick_rate as f32;
+
+    for mut node in &mut playhead_query {
+        node.left = Val::Percent(tick.0.min(total_ticks) as f32 / total_ticks as f32 * 100.0);
+    }
+
+    let status = format!(
+        "{} {}x  {} / {}   SPACE play/pause  [ ] speed  , . step  ARROWS skip  {} menu",
+        if paused.0 { "PAUSED" } else { "PLAYING" },
+        SPEEDS[viewer.speed_index],
+        format_clock(tick.0 as f32 / tick_rate),
+        format_clock(total_ticks as f32 / tick_rate),
+        key_label(bindings.key(Action::Pause)),
+    );
+    for mut text in &mut status_query {
+        if text.0 != status {
+            *text = Text::new(status.clone());
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn cleanup_replay_viewer(
+    mut commands: Commands,
+    entities: Query<Entity, Or<(With<GameUI>, With<ReplayViewerUI>)>>,
+    mut score: ResMut<Score>,
+    mut puck_timer: ResMut<PuckDelayTimer>,
+    mut paused: ResMut<GamePaused>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+) {
+    reset_match(&mut commands, entities.iter(), &mut score, &mut puck_timer);
+    paused.0 = false;
+    virtual_time.set_relative_speed(1.0);
+    commands.remove_resource::<ReplayViewer>();
+}
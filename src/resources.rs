@@ -1,4 +1,13 @@
 use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::components::Side;
+use crate::config::GameConfig;
+use crate::constants::*;
 
 #[derive(Resource, Default)]
 pub struct Score {
@@ -18,7 +27,421 @@ pub enum GameState {
     StartScreen,
     Countdown,
     Playing,
+    GameOver,
+    ReplayViewer,
+    Connecting, // Hosting and waiting for an opponent, or joining a host
+    Lobby, // Finding a game on the local network and getting ready
+    Controllers, // Choosing which controller plays which side
+    Controls, // Changing the keys
+    Settings, // Window and resolution, and the way to the controls
 }
 
 #[derive(Resource)]
-pub struct CountdownTimer(pub Timer, pub u32); // Timer and current count
\ No newline at end of file
+pub struct CountdownTimer(pub Timer, pub u32); // Timer and current count
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    // Seconds between the CPU re-reading the puck
+    pub fn reaction_delay(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.35,
+            Difficulty::Normal => 0.18,
+            Difficulty::Hard => 0.05,
+        }
+    }
+
+    // Largest random miss (in pixels) added to the predicted intercept
+    pub fn prediction_error(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 60.0,
+            Difficulty::Normal => 25.0,
+            Difficulty::Hard => 5.0,
+        }
+    }
+
+    // How many wall bounces the CPU can see ahead when predicting the puck
+    pub fn bounce_lookahead(&self) -> u32 {
+        match self {
+            Difficulty::Easy => 0,
+            Difficulty::Normal => 1,
+            Difficulty::Hard => u32::MAX,
+        }
+    }
+
+    // Whether the CPU strikes off-center to steer its returns
+    pub fn aims_shots(&self) -> bool {
+        *self != Difficulty::Easy
+    }
+
+    // Share of a player's paddle speed the CPU can move at, never faster
+    pub fn speed_factor(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.55,
+            Difficulty::Normal => 0.8,
+            Difficulty::Hard => 1.0,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "EASY",
+            Difficulty::Normal => "NORMAL",
+            Difficulty::Hard => "HARD",
+        }
+    }
+}
+
+// Classic, the original feel and the default, never lets the puck drop below
+// puck_speed, though paddle swings still speed it up. Realistic adds drag and
+// lossy wall and paddle bounces that slow it down until it comes to rest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PhysicsMode {
+    #[default]
+    Classic,
+    Realistic,
+}
+
+impl PhysicsMode {
+    pub fn linear_drag(&self, config: &GameConfig) -> f32 {
+        match self {
+            PhysicsMode::Classic => 0.0,
+            PhysicsMode::Realistic => config.puck_linear_drag,
+        }
+    }
+
+    pub fn wall_restitution(&self, config: &GameConfig) -> f32 {
+        match self {
+            PhysicsMode::Classic => 1.0,
+            PhysicsMode::Realistic => config.wall_restitution,
+        }
+    }
+}
+
+// Match options that can be changed from the start screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    MatchLength,
+    TargetScore,
+    WinByTwo,
+    Physics,
+}
+
+const TARGET_SCORES: [u32; 4] = [3, 5, 7, 10];
+const MATCH_LENGTHS: [Option<f32>; 4] = [None, Some(120.0), Some(180.0), Some(300.0)];
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct MatchSettings {
+    pub cpu_side: Option<Side>, // None for two human players
+    pub difficulty: Difficulty,
+    pub target_score: u32,
+    pub win_by_two: bool,
+    pub match_length: Option<f32>, // Seconds for a timed match, None for first to target_score
+    pub physics: PhysicsMode,
+    pub tick_rate: f64,
+    pub seed: Option<u64>, // Fixed RNG seed, None for a fresh one every match
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        Self {
+            cpu_side: None,
+            difficulty: Difficulty::default(),
+            target_score: 7,
+            win_by_two: false,
+            match_length: None,
+            physics: PhysicsMode::default(),
+            tick_rate: DEFAULT_TICK_RATE,
+            seed: None,
+        }
+    }
+}
+
+impl MatchSettings {
+    // The side that has won the match with this score, if any
+    pub fn winner(&self, score: &Score, clock: &MatchClock) -> Option<Side> {
+        if self.match_length.is_some() {
+            // Timed matches are only decided once the clock has run out, after which
+            // the first goal of sudden-death overtime wins
+            if !clock.overtime || score.left == score.right {
+                return None;
+            }
+            return Some(if score.left > score.right { Side::Left } else { Side::Right });
+        }
+
+        let margin = if self.win_by_two { 2 } else { 1 };
+        if score.left >= self.target_score && score.left >= score.right + margin {
+            Some(Side::Left)
+        } else if score.right >= self.target_score && score.right >= score.left + margin {
+            Some(Side::Right)
+        } else {
+            None
+        }
+    }
+
+    pub fn cycle_rule(&mut self, rule: Rule) {
+        match rule {
+            Rule::TargetScore => {
+                let index = TARGET_SCORES.iter().position(|&s| s == self.target_score).unwrap_or(0);
+                self.target_score = TARGET_SCORES[(index + 1) % TARGET_SCORES.len()];
+            }
+            Rule::WinByTwo => self.win_by_two = !self.win_by_two,
+            Rule::Physics => {
+                self.physics = match self.physics {
+                    PhysicsMode::Classic => PhysicsMode::Realistic,
+                    PhysicsMode::Realistic => PhysicsMode::Classic,
+                };
+            }
+            Rule::MatchLength => {
+                let index = MATCH_LENGTHS.iter().position(|&l| l == self.match_length).unwrap_or(0);
+                self.match_length = MATCH_LENGTHS[(index + 1) % MATCH_LENGTHS.len()];
+            }
+        }
+    }
+
+    pub fn rule_label(&self, rule: Rule) -> String {
+        match rule {
+            Rule::TargetScore => format!("FIRST TO {}", self.target_score),
+            Rule::WinByTwo => format!("WIN BY 2: {}", if self.win_by_two { "ON" } else { "OFF" }),
+            Rule::MatchLength => match self.match_length {
+                Some(length) => format!("TIMED: {} MIN", length / 60.0),
+                None => "NO TIME LIMIT".to_string(),
+            },
+            Rule::Physics => match self.physics {
+                PhysicsMode::Classic => "PHYSICS: CLASSIC".to_string(),
+                PhysicsMode::Realistic => "PHYSICS: REAL".to_string(),
+            },
+        }
+    }
+}
+
+// The side played with the mouse, if any. Only this machine's choice, so it's
+// not part of the match settings.
+#[derive(Resource, Default)]
+pub struct MouseControl(pub Option<Side>);
+
+impl MouseControl {
+    pub fn cycle(&mut self) {
+        self.0 = match self.0 {
+            None => Some(Side::Left),
+            Some(Side::Left) => Some(Side::Right),
+            Some(Side::Right) => None,
+        };
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self.0 {
+            None => "MOUSE: OFF",
+            Some(Side::Left) => "MOUSE: RED",
+            Some(Side::Right) => "MOUSE: BLUE",
+        }
+    }
+}
+
+// Settings given on the command line:
+//   --seed <n>       play every match with the same RNG seed
+//   --tick-rate <hz> simulation ticks per second
+//   --replay <file>  watch a recorded match instead of playing
+//   --port <port>    port to host networked matches on
+//   --join <address> host to connect to with JOIN GAME, as host:port
+//   --rollback       play networked matches peer to peer with rollback
+//   --net-latency <ms> delay every packet sent, for testing on one machine
+//   --net-loss <percent> drop this share of packets sent
+//   --spectators <port> let spectators watch matches played here
+//   --spectate <address> watch the match at another game's spectator port
+//   --name <name>    name shown to other players in the lobby
+#[derive(Resource, Clone, Default)]
+pub struct LaunchOptions {
+    pub seed: Option<u64>,
+    pub tick_rate: Option<f64>,
+    pub replay: Option<PathBuf>,
+    pub port: Option<u16>,
+    pub join: Option<String>,
+    pub rollback: bool,
+    pub net_latency: Duration,
+    pub net_loss: f32, // 0 to 1
+    pub spectators: Option<u16>,
+    pub spectate: Option<String>,
+    pub name: Option<String>,
+}
+
+impl LaunchOptions {
+    // Stops at the first argument that's unknown or missing its value, before
+    // the logger is running, so the caller reports the error itself
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+            match flag {
+                "--rollback" => options.rollback = true,
+                "--seed" => options.seed = Some(parse_flag(flag, args.next(), "a whole number")?),
+                "--tick-rate" => {
+                    let rate: f64 = parse_flag(flag, args.next(), "a positive number")?;
+                    check_tick_rate(rate).map_err(|e| format!("--tick-rate {e}"))?;
+                    options.tick_rate = Some(rate);
+                }
+                "--replay" => options.replay = Some(flag_value(flag, args.next(), "a file")?.into()),
+                "--port" => options.port = Some(parse_flag(flag, args.next(), "a port number")?),
+                "--join" => options.join = Some(flag_value(flag, args.next(), "an address")?),
+                "--spectators" => options.spectators = Some(parse_flag(flag, args.next(), "a port number")?),
+                "--spectate" => options.spectate = Some(flag_value(flag, args.next(), "an address")?),
+                "--name" => options.name = Some(flag_value(flag, args.next(), "a name")?),
+                "--net-latency" => {
+                    let ms = parse_flag(flag, args.next(), "a whole number of milliseconds")?;
+                    options.net_latency = Duration::from_millis(ms);
+                }
+                "--net-loss" => {
+                    let percent: f32 = parse_flag(flag, args.next(), "a percentage")?;
+                    if !(0.0..=100.0).contains(&percent) {
+                        return Err(format!("--net-loss needs a percentage from 0 to 100, got {percent}"));
+                    }
+                    options.net_loss = percent / 100.0;
+                }
+                _ => return Err(format!("Unknown argument {arg}")),
+            }
+        }
+        Ok(options)
+    }
+
+    // A bare host name or IP joins on the default port
+    pub fn join_address(&self) -> String {
+        with_default_port(self.join.as_deref(), DEFAULT_PORT)
+    }
+
+    pub fn spectate_address(&self) -> String {
+        with_default_port(self.spectate.as_deref(), DEFAULT_SPECTATOR_PORT)
+    }
+
+    // Defaults to the name of the user playing
+    pub fn player_name(&self) -> String {
+        self.name.clone()
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+            .unwrap_or_else(|| "PLAYER".to_string())
+    }
+
+    pub fn match_settings(&self) -> MatchSettings {
+        MatchSettings {
+            seed: self.seed,
+            tick_rate: self.tick_rate.unwrap_or(DEFAULT_TICK_RATE),
+            ..default()
+        }
+    }
+}
+
+fn with_default_port(address: Option<&str>, port: u16) -> String {
+    match address {
+        Some(address) if address.contains(':') => address.to_string(),
+        Some(host) => format!("{host}:{port}"),
+        None => format!("127.0.0.1:{port}"),
+    }
+}
+
+// Bevy can't run a fixed timestep of zero, infinity or NaN
+pub fn check_tick_rate(rate: f64) -> Result<(), String> {
+    if !(rate.is_finite() && rate > 0.0 && rate <= MAX_TICK_RATE) {
+        return Err(format!("must be above 0 and at most {MAX_TICK_RATE}, got {rate}"));
+    }
+    Ok(())
+}
+
+// The argument after a flag. Another flag there means the value was left out.
+fn flag_value(flag: &str, value: Option<String>, needs: &str) -> Result<String, String> {
+    value
+        .filter(|value| !value.starts_with("--"))
+        .ok_or_else(|| format!("{flag} needs {needs}"))
+}
+
+fn parse_flag<T: FromStr>(flag: &str, value: Option<String>, needs: &str) -> Result<T, String> {
+    let value = flag_value(flag, value, needs)?;
+    value.parse().map_err(|_| format!("{flag} needs {needs}, got {value}"))
+}
+
+// Number of simulation ticks run so far this match
+#[derive(Resource, Default)]
+pub struct SimulationTick(pub u64);
+
+// Seconds of the current tick the puck has already moved through, when it met a
+// paddle part way. Set by check_paddle_collision and used up by move_puck.
+#[derive(Resource, Default)]
+pub struct PuckTimeUsed(pub f32);
+
+// Sent by check_score whenever a goal goes in
+#[derive(Message)]
+pub struct GoalScored {
+    pub side: Side, // The side that scored
+}
+
+// Time spent in play this match, not counting pauses
+#[derive(Resource, Default)]
+pub struct MatchTime(pub f32);
+
+// Time left in a timed match. Once it runs out with the score tied the match
+// goes to sudden-death overtime.
+#[derive(Resource, Default)]
+pub struct MatchClock {
+    pub remaining: Option<f32>,
+    pub overtime: bool,
+}
+
+impl MatchClock {
+    pub fn new(settings: &MatchSettings) -> Self {
+        Self {
+            remaining: settings.match_length,
+            overtime: false,
+        }
+    }
+}
+
+// The only source of randomness in a match. Everything random draws from here so
+// the same seed and inputs always replay the same match. ChaCha gives the same
+// numbers on every platform and its state can be saved into snapshots.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct GameRng {
+    pub seed: u64,
+    pub rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    // Seeded from settings if set, otherwise from a fresh random seed
+    pub fn for_match(settings: &MatchSettings) -> Self {
+        Self::new(settings.seed.unwrap_or_else(|| rand::thread_rng().r#gen()))
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<LaunchOptions, String> {
+        LaunchOptions::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn tick_rate_must_be_a_usable_number() {
+        assert_eq!(args(&["--tick-rate", "120"]).unwrap().tick_rate, Some(120.0));
+        for rate in ["nan", "inf", "-inf", "0", "-30", "1e12"] {
+            assert!(args(&["--tick-rate", rate]).is_err(), "--tick-rate {rate} was accepted");
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::components::*;
+use crate::config::GameConfig;
+use crate::lobby::Lobby;
+use crate::net::{NET_VERSION, NetSession};
+use crate::replay::ReplayRecorder;
+use crate::resources::*;
+use crate::snapshot::{self, WorldSnapshot};
+use crate::systems::reset_match;
+
+// Peer-to-peer matches with rollback. Both peers run the whole simulation and
+// only send their inputs. Until the other peer's input for a tick arrives it is
+// guessed to be the same as their last one, and if the guess turns out wrong the
+// match is put back to that tick and played forward again with the real input.
+
+// How far ahead of the other peer's inputs a peer may run before it waits
+const MAX_PREDICTION_TICKS: u64 = 30;
+const INPUTS_PER_PACKET: usize = 64;
+
+#[derive(Serialize, Deserialize)]
+pub enum PeerMessage {
+    Hello { version: u32 },
+    Start { settings: MatchSettings, config: GameConfig, seed: u64 },
+    // Doubles as the heartbeat. A higher match number than the receiver's asks
+    // for a rematch.
+    Inputs {
+        match_index: u32,
+        first_tick: u64,
+        inputs: Vec<[f32; 2]>,
+        received: u64, // How many of the receiver's inputs have arrived
+    },
+    Ready(bool), // Heartbeat in the lobby
+    // Sent every frame once either player has paused. The higher count is the
+    // latest change, and if both changed at once the host's wins.
+    Pause { count: u32, paused: bool },
+    Goodbye,
+}
+
+#[derive(Resource)]
+pub struct RollbackSession {
+    pub match_index: u32,
+    base_seed: u64, // Each match plays with base_seed + match_index
+    local_inputs: Vec<Vec2>, // By tick
+    remote_inputs: Vec<Vec2>, // Confirmed, by tick
+    played_remote: Vec<Vec2>, // The remote input each tick was last simulated with
+    remote_received: u64, // How many of our inputs the peer has
+    snapshots: VecDeque<WorldSnapshot>, // The state at the start of each recent tick
+    rollback_to: Option<u64>,
+    pause_count: u32, // Times the match has been paused or resumed, by either peer
+    paused: bool, // As both peers last agreed
+    pub saved_settings: MatchSettings, // Put back when the session ends
+}
+
+impl RollbackSession {
+    pub fn new(base_seed: u64, saved_settings: MatchSettings) -> Self {
+        Self {
+            match_index: 0,
+            base_seed,
+            local_inputs: Vec::new(),
+            remote_inputs: Vec::new(),
+            played_remote: Vec::new(),
+            remote_received: 0,
+            snapshots: VecDeque::new(),
+            rollback_to: None,
+            pause_count: 0,
+            paused: false,
+            saved_settings,
+        }
+    }
+
+    // Ticks before this have inputs from both peers
+    fn confirmed_ticks(&self) -> u64 {
+        self.remote_inputs.len() as u64
+    }
+
+    fn snapshot_at(&self, tick: u64) -> Option<&WorldSnapshot> {
+        self.snapshots.iter().find(|snapshot| snapshot.tick == tick)
+    }
+
+    // The peer's inputs from first_tick on, with played the ticks simulated so
+    // far. Any already played with a wrong guess are rolled back to before the
+    // next ticks run.
+    pub fn receive_inputs(&mut self, first_tick: u64, inputs: impl IntoIterator<Item = Vec2>, played: u64) {
+        for (tick, input) in (first_tick..).zip(inputs) {
+            if tick != self.remote_inputs.len() as u64 {
+                continue;
+            }
+            self.remote_inputs.push(input);
+
+            if tick < played && self.played_remote.get(tick as usize) != Some(&input) {
+                self.rollback_to = Some(self.rollback_to.map_or(tick, |earliest| earliest.min(tick)));
+            }
+        }
+    }
+}
+
+// Both peers start every match with nothing played, and the same seed
+pub fn start_rollback_match(mut session: ResMut<RollbackSession>, mut settings: ResMut<MatchSettings>) {
+    session.match_index += 1;
+    session.local_inputs.clear();
+    session.remote_inputs.clear();
+    session.played_remote.clear();
+    session.remote_received = 0;
+    session.snapshots.clear();
+    session.rollback_to = None;
+    session.pause_count = 0;
+    session.paused = false;
+    settings.cpu_side = None;
+    settings.seed = Some(session.base_seed.wrapping_add(session.match_index as u64));
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn receive_as_peer(
+    mut net: ResMut<NetSession>,
+    mut session: ResMut<RollbackSession>,
+    real_time: Res<Time<Real>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut settings: ResMut<MatchSettings>,
+    mut config: ResMut<GameConfig>,
+    tick: Option<Res<SimulationTick>>,
+    mut commands: Commands,
+    game_entities: Query<Entity, Or<(With<GameUI>, With<GameOverUI>)>>,
+    mut score: ResMut<Score>,
+    mut puck_timer: ResMut<PuckDelayTimer>,
+    mut lobby: Option<ResMut<Lobby>>,
+    mut paused: ResMut<GamePaused>,
+) {
+    let now = real_time.elapsed();
+    let session = &mut *session;
+    for (from, message) in net.receive::<PeerMessage>(now) {
+        match message {
+            PeerMessage::Hello { version } => {
+                if version != NET_VERSION {
+                    warn!("{from} is running a different version of the game, ignoring it");
+                } else if net.peer.is_none() {
+                    net.accept_peer(from, now);
+                    // From the lobby the match waits until both players are ready
+                    if *state.get() != GameState::Lobby {
+                        next_state.set(GameState::Countdown);
+                    }
+                }
+            }
+            PeerMessage::Start { settings: peer_settings, config: peer_config, seed } => {
+                if matches!(state.get(), GameState::Connecting | GameState::Lobby) {
+                    *settings = peer_settings;
+                    config.set_if_neq(peer_config);
+                    session.base_seed = seed;
+                    next_state.set(GameState::Countdown);
+                }
+            }
+            PeerMessage::Inputs { match_index, first_tick, inputs, received } => {
+                if match_index > session.match_index && *state.get() == GameState::GameOver {
+                    reset_match(&mut commands, game_entities.iter(), &mut score, &mut puck_timer);
+                    next_state.set(GameState::Countdown);
+                }
+                if match_index != session.match_index {
+                    continue;
+                }
+
+                session.remote_received = session.remote_received.max(received);
+                let played = tick.as_ref().map_or(0, |tick| tick.0);
+                session.receive_inputs(first_tick, inputs.into_iter().map(Vec2::from), played);
+            }
+            PeerMessage::Pause { count, paused: peer_paused } => {
+                let host_wins_tie = net.local_side == Side::Right && peer_paused != session.paused;
+                let newer = count > session.pause_count || (count == session.pause_count && host_wins_tie);
+                if *state.get() == GameState::Playing && newer {
+                    session.pause_count = count;
+                    session.paused = peer_paused;
+                    paused.0 = peer_paused;
+                }
+            }
+            PeerMessage::Ready(ready) => {
+                if let Some(lobby) = lobby.as_mut() {
+                    lobby.peer_ready = ready;
+                }
+            }
+            // A player leaving the lobby frees it up for someone else
+            PeerMessage::Goodbye => match lobby.as_mut() {
+                Some(lobby) if net.local_side == Side::Left => {
+                    lobby.peer_ready = false;
+                    net.drop_peer();
+                }
+                _ => net.closed = true,
+            },
+        }
+    }
+}
+
+// Rewinds to the first tick that was played with a wrong guess and plays every
+// tick since again, before this frame's ticks run
+pub fn roll_back(world: &mut World) {
+    let Some(rollback_to) = world.resource_mut::<RollbackSession>().rollback_to.take() else {
+        return;
+    };
+    let Some(snapshot) = world.resource::<RollbackSession>().snapshot_at(rollback_to).cloned() else {
+        warn!("No saved state to roll back to tick {rollback_to}");
+        return;
+    };
+
+    let current = world.resource::<SimulationTick>().0;
+    snapshot::restore(world, &snapshot);
+    if let Some(mut recorder) = world.get_resource_mut::<ReplayRecorder>() {
+        recorder.0.rewind(rollback_to);
+    }
+    while world.resource::<SimulationTick>().0 < current {
+        snapshot::run_simulation_tick(world);
+    }
+}
+
+// First in every tick
+pub fn save_rollback_state(world: &mut World) {
+    let Some(snapshot) = snapshot::capture(world) else {
+        return;
+    };
+    let mut session = world.resource_mut::<RollbackSession>();
+    while session.snapshots.back().is_some_and(|saved| saved.tick >= snapshot.tick) {
+        session.snapshots.pop_back();
+    }
+    session.snapshots.push_back(snapshot);
+    // Nothing older than the confirmed ticks will ever be rolled back to
+    let confirmed = session.confirmed_ticks();
+    while session.snapshots.front().is_some_and(|saved| saved.tick < confirmed) {
+        session.snapshots.pop_front();
+    }
+}
+
+// Fresh ticks take the local input from the keyboard and guess the remote one.
+// Ticks being played again use the inputs they had, with any that have since
+// arrived from the peer.
+pub fn apply_rollback_inputs(
+    mut session: ResMut<RollbackSession>,
+    tick: Res<SimulationTick>,
+    mut query: Query<(&mut PaddleInput, Has<RemotePaddle>), With<Paddle>>,
+) {
+    let index = tick.0 as usize;
+    let remote_input = match session.remote_inputs.get(index) {
+        Some(input) => *input,
+        None => session.remote_inputs.last().copied().unwrap_or_default(),
+    };
+    session.played_remote.truncate(index);
+    session.played_remote.push(remote_input);
+
+    for (mut input, remote) in &mut query {
+        if remote {
+            input.0 = remote_input;
+        } else if let Some(played) = session.local_inputs.get(index) {
+            input.0 = *played;
+        } else {
+            session.local_inputs.push(input.0);
+        }
+    }
+}
+
+// Run condition: hold the simulation when it has got too far ahead of the peer
+pub fn rollback_stalled(session: Option<Res<RollbackSession>>, tick: Res<SimulationTick>) -> bool {
+    session.is_some_and(|session| tick.0 >= session.confirmed_ticks() + MAX_PREDICTION_TICKS)
+}
+
+// Our inputs the peer hasn't got yet, sent every frame, along with whether the
+// match is paused
+#[allow(clippy::too_many_arguments)]
+pub fn send_as_peer(
+    mut net: ResMut<NetSession>,
+    mut session: ResMut<RollbackSession>,
+    paused: Res<GamePaused>,
+    state: Res<State<GameState>>,
+    settings: Res<MatchSettings>,
+    config: Res<GameConfig>,
+    real_time: Res<Time<Real>>,
+    lobby: Option<Res<Lobby>>,
+) {
+    let now = real_time.elapsed();
+    match state.get() {
+        GameState::Connecting => {
+            if net.heartbeat_due(now) {
+                net.send(&PeerMessage::Hello { version: NET_VERSION }, now);
+            }
+        }
+        GameState::Lobby => {
+            if net.heartbeat_due(now) {
+                let message = match lobby {
+                    Some(lobby) if net.connected => PeerMessage::Ready(lobby.ready),
+                    _ => PeerMessage::Hello { version: NET_VERSION },
+                };
+                net.send(&message, now);
+            }
+        }
+        state => {
+            // The joining peer needs the rules, config and seed before it can start
+            let hosting = net.local_side == Side::Left;
+            if hosting && *state == GameState::Countdown && session.match_index == 1 && net.heartbeat_due(now) {
+                let start = PeerMessage::Start {
+                    settings: settings.clone(),
+                    config: config.clone(),
+                    seed: session.base_seed,
+                };
+                net.send(&start, now);
+            }
+
+            // Oldest first, so the peer's inputs never have a gap in them
+            let first_tick = (session.remote_received as usize).min(session.local_inputs.len());
+            let last_tick = (first_tick + INPUTS_PER_PACKET).min(session.local_inputs.len());
+            let inputs = session.local_inputs[first_tick..last_tick].iter().map(|input| input.to_array()).collect();
+            let message = PeerMessage::Inputs {
+                match_index: session.match_index,
+                first_tick: first_tick as u64,
+                inputs,
+                received: session.confirmed_ticks(),
+            };
+            net.send(&message, now);
+
+            // A pause or resume here that the peer hasn't heard about yet
+            if *state == GameState::Playing && paused.0 != session.paused {
+                session.pause_count += 1;
+                session.paused = paused.0;
+            }
+            if session.pause_count > 0 {
+                net.send(&PeerMessage::Pause { count: session.pause_count, paused: session.paused }, now);
+            }
+        }
+    }
+}
+
+// The match only ends on a tick both peers have the inputs for, so a goal that
+// was only guessed at can't end it. The confirmed state is put back first so
+// both peers show the same final score.
+pub fn end_rollback_match_when_won(world: &mut World) {
+    let session = world.resource::<RollbackSession>();
+    let confirmed_ticks = session.confirmed_ticks();
+    // Nothing played yet has been guessed
+    if world.resource::<SimulationTick>().0 <= confirmed_ticks {
+        let winner = world.resource::<MatchSettings>().winner(world.resource::<Score>(), world.resource::<MatchClock>());
+        if winner.is_some() {
+            world.resource_mut::<NextState<GameState>>().set(GameState::GameOver);
+        }
+        return;
+    }
+
+    let Some(confirmed) = session.snapshot_at(confirmed_ticks).cloned() else {
+        return;
+    };
+    let score = Score {
+        left: confirmed.score.0,
+        right: confirmed.score.1,
+    };
+    let clock = MatchClock {
+        remaining: confirmed.clock_remaining,
+        overtime: confirmed.overtime,
+    };
+    if world.resource::<MatchSettings>().winner(&score, &clock).is_some() {
+        snapshot::restore(world, &confirmed);
+        if let Some(mut recorder) = world.get_resource_mut::<ReplayRecorder>() {
+            recorder.0.rewind(confirmed.tick);
+        }
+        world.resource_mut::<NextState<GameState>>().set(GameState::GameOver);
+    }
+}
@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use crate::components::*;
+use crate::resources::*;
+
+// Everything the simulation needs to carry on from a given tick. Restoring one and
+// running the same inputs reproduces the original match exactly.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub tick: u64,
+    pub puck: BodySnapshot,
+    pub paddles: Vec<PaddleSnapshot>,
+    pub score: (u32, u32),
+    pub serve_delay: TimerSnapshot,
+    pub clock_remaining: Option<f32>,
+    pub overtime: bool,
+    pub match_time: f32,
+    pub rng: GameRng,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct BodySnapshot {
+    pub position: [f32; 2],
+    pub previous_position: [f32; 2],
+    pub velocity: [f32; 2],
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PaddleSnapshot {
+    pub side: Side,
+    pub body: BodySnapshot,
+    pub input: [f32; 2],
+    pub cpu: Option<(TimerSnapshot, [f32; 2])>, // Reaction timer and target
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct TimerSnapshot {
+    pub elapsed: Duration,
+    pub duration: Duration,
+    pub repeating: bool,
+}
+
+impl TimerSnapshot {
+    fn capture(timer: &Timer) -> Self {
+        Self {
+            elapsed: timer.elapsed(),
+            duration: timer.duration(),
+            repeating: timer.mode() == TimerMode::Repeating,
+        }
+    }
+
+    fn restore(&self) -> Timer {
+        let mode = if self.repeating { TimerMode::Repeating } else { TimerMode::Once };
+        // Ticked rather than set, so a timer that had run out reports finished
+        let mut timer = Timer::new(self.duration, mode);
+        timer.tick(self.elapsed);
+        timer
+    }
+}
+
+fn capture_body(position: &Position, previous: &PreviousPosition, velocity: &Velocity) -> BodySnapshot {
+    BodySnapshot {
+        position: position.0.to_array(),
+        previous_position: previous.0.to_array(),
+        velocity: velocity.0.to_array(),
+    }
+}
+
+fn restore_body(body: &BodySnapshot, position: &mut Position, previous: &mut PreviousPosition, velocity: &mut Velocity) {
+    position.0 = Vec2::from_array(body.position);
+    previous.0 = Vec2::from_array(body.previous_position);
+    velocity.0 = Vec2::from_array(body.velocity);
+}
+
+// None until a match has been set up
+pub fn capture(world: &mut World) -> Option<WorldSnapshot> {
+    let puck = world
+        .query_filtered::<(&Position, &PreviousPosition, &Velocity), With<Puck>>()
+        .single(world)
+        .ok()
+        .map(|(position, previous, velocity)| capture_body(position, previous, velocity))?;
+
+    let mut paddles: Vec<PaddleSnapshot> = world
+        .query::<(&Paddle, &Position, &PreviousPosition, &Velocity, &PaddleInput, Option<&CpuPaddle>)>()
+        .iter(world)
+        .map(|(paddle, position, previous, velocity, input, cpu)| PaddleSnapshot {
+            side: paddle.side,
+            body: capture_body(position, previous, velocity),
+            input: input.0.to_array(),
+            cpu: cpu.map(|cpu| (TimerSnapshot::capture(&cpu.reaction_timer), cpu.target.to_array())),
+        })
+        .collect();
+    paddles.sort_by_key(|paddle| paddle.side == Side::Right);
+
+    let score = world.resource::<Score>();
+    let clock = world.resource::<MatchClock>();
+    Some(WorldSnapshot {
+        tick: world.resource::<SimulationTick>().0,
+        puck,
+        paddles,
+        score: (score.left, score.right),
+        serve_delay: TimerSnapshot::capture(&world.resource::<PuckDelayTimer>().0),
+        clock_remaining: clock.remaining,
+        overtime: clock.overtime,
+        match_time: world.resource::<MatchTime>().0,
+        rng: world.resource::<GameRng>().clone(),
+    })
+}
+
+pub fn restore(world: &mut World, snapshot: &WorldSnapshot) {
+    let mut pucks = world.query_filtered::<(&mut Position, &mut PreviousPosition, &mut Velocity), With<Puck>>();
+    for (mut position, mut previous, mut velocity) in pucks.iter_mut(world) {
+        restore_body(&snapshot.puck, &mut position, &mut previous, &mut velocity);
+    }
+
+    let mut paddles = world.query::<(
+        &Paddle,
+        &mut Position,
+        &mut PreviousPosition,
+        &mut Velocity,
+        &mut PaddleInput,
+        Option<&mut CpuPaddle>,
+    )>();
+    for (paddle, mut position, mut previous, mut velocity, mut input, cpu) in paddles.iter_mut(world) {
+        let Some(saved) = snapshot.paddles.iter().find(|saved| saved.side == paddle.side) else {
+            continue;
+        };
+        restore_body(&saved.body, &mut position, &mut previous, &mut velocity);
+        input.0 = Vec2::from_array(saved.input);
+        if let (Some(mut cpu), Some((reaction_timer, target))) = (cpu, &saved.cpu) {
+            cpu.reaction_timer = reaction_timer.restore();
+            cpu.target = Vec2::from_array(*target);
+        }
+    }
+
+    world.resource_mut::<SimulationTick>().0 = snapshot.tick;
+    *world.resource_mut::<Score>() = Score {
+        left: snapshot.score.0,
+        right: snapshot.score.1,
+    };
+    world.resource_mut::<PuckDelayTimer>().0 = snapshot.serve_delay.restore();
+    *world.resource_mut::<MatchClock>() = MatchClock {
+        remaining: snapshot.clock_remaining,
+        overtime: snapshot.overtime,
+    };
+    world.resource_mut::<MatchTime>().0 = snapshot.match_time;
+    world.insert_resource(snapshot.rng.clone());
+}
+
+// Present while run_simulation_tick is playing a tick again. Whatever was drawn or
+// announced for it the first time, like the goal replay, isn't redone.
+#[derive(Resource)]
+pub struct Resimulating;
+
+// Runs one simulation tick right now, outside the normal fixed-timestep loop. Used
+// to re-simulate after restoring a snapshot. Like Bevy's own fixed loop, Time is
+// swapped to the fixed clock while the tick runs.
+pub fn run_simulation_tick(world: &mut World) {
+    let timestep = world.resource::<Time<Fixed>>().timestep();
+    world.resource_mut::<Time<Fixed>>().advance_by(timestep);
+    *world.resource_mut::<Time>() = world.resource::<Time<Fixed>>().as_generic();
+
+    // The simulation's run condition checks for pause, and this tick was asked for
+    let was_paused = world.resource::<GamePaused>().0;
+    world.resource_mut::<GamePaused>().0 = false;
+    world.insert_resource(Resimulating);
+    world.run_schedule(FixedUpdate);
+    world.remove_resource::<Resimulating>();
+    world.resource_mut::<GamePaused>().0 = was_paused;
+
+    *world.resource_mut::<Time>() = world.resource::<Time<Virtual>>().as_generic();
+}
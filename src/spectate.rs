@@ -0,0 +1,191 @@
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+use bevy::prelude::*;
+use crate::components::*;
+use crate::config::GameConfig;
+use crate::net::*;
+use crate::resources::*;
+use crate::snapshot;
+
+// Spectators watch a match played on another machine, or in another window. A
+// match started with --spectators offers every spectator the full state to join
+// from, then streams the same per-frame state a networked client gets. On the
+// watching side a spectator is a networked client with no paddle of its own.
+
+struct Spectator {
+    address: SocketAddr,
+    match_id: Option<u64>, // The match it says it is following
+    last_heard: Duration,
+}
+
+#[derive(Resource)]
+pub struct SpectatorServer {
+    socket: UdpSocket,
+    spectators: Vec<Spectator>,
+    match_id: u64, // Bumped for every match played, so spectators notice a new one
+}
+
+pub fn start_spectator_server(mut commands: Commands, options: Res<LaunchOptions>) {
+    let Some(port) = options.spectators else {
+        return;
+    };
+    let socket = match UdpSocket::bind(("0.0.0.0", port)).and_then(|socket| socket.set_nonblocking(true).map(|_| socket)) {
+        Ok(socket) => socket,
+        Err(error) => {
+            error!("Couldn't accept spectators on port {port}: {error}");
+            return;
+        }
+    };
+    info!("Accepting spectators on port {port}");
+    commands.insert_resource(SpectatorServer {
+        socket,
+        spectators: Vec::new(),
+        match_id: 0,
+    });
+}
+
+pub fn next_spectated_match(mut server: ResMut<SpectatorServer>) {
+    server.match_id += 1;
+}
+
+// Runs after the frame's ticks. Spectators following the current match get its
+// state, any others are offered a snapshot to join from.
+pub fn serve_spectators(world: &mut World) {
+    let now = world.resource::<Time<Real>>().elapsed();
+    let playing = *world.resource::<State<GameState>>().get() == GameState::Playing;
+
+    let mut server = world.resource_mut::<SpectatorServer>();
+    let mut buffer = [0u8; 1024];
+    loop {
+        let (length, from) = match server.socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(_) => continue,
+        };
+        match decode::<ClientMessage>(&buffer[..length]) {
+            Some(ClientMessage::Watch { version, match_id }) if version == NET_VERSION => {
+                match server.spectators.iter_mut().find(|spectator| spectator.address == from) {
+                    Some(spectator) => {
+                        spectator.match_id = match_id;
+                        spectator.last_heard = now;
+                    }
+                    None => {
+                        info!("Spectator {from} joined");
+                        server.spectators.push(Spectator {
+                            address: from,
+                            match_id,
+                            last_heard: now,
+                        });
+                    }
+                }
+            }
+            Some(ClientMessage::Goodbye) => server.spectators.retain(|spectator| spectator.address != from),
+            _ => {}
+        }
+    }
+    server.spectators.retain(|spectator| now.saturating_sub(spectator.last_heard) <= TIMEOUT);
+    if server.spectators.is_empty() {
+        return;
+    }
+
+    let match_id = server.match_id;
+    let wants_snapshot = server.spectators.iter().any(|spectator| spectator.match_id != Some(match_id));
+    let (state, spectate) = if playing {
+        let state = NetState::capture(world, 0).map(HostMessage::State);
+        let spectate = match snapshot::capture(world) {
+            Some(snapshot) if wants_snapshot => Some(HostMessage::Spectate {
+                match_id,
+                settings: world.resource::<MatchSettings>().clone(),
+                config: world.resource::<GameConfig>().clone(),
+                snapshot: Box::new(snapshot),
+            }),
+            _ => None,
+        };
+        (state.as_ref().and_then(encode), spectate.as_ref().and_then(encode))
+    } else {
+        (encode(&HostMessage::Heartbeat), None)
+    };
+
+    let server = world.resource::<SpectatorServer>();
+    for spectator in &server.spectators {
+        let packet = match &spectate {
+            Some(spectate) if spectator.match_id != Some(match_id) => spectate,
+            _ => match &state {
+                Some(state) => state,
+                None => continue,
+            },
+        };
+        let _ = server.socket.send_to(packet, spectator.address);
+    }
+}
+
+// Watching Systems
+
+#[allow(clippy::type_complexity)]
+pub fn handle_spectate_button(
+    mut query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<SpectateButton>)>,
+    mut commands: Commands,
+    options: Res<LaunchOptions>,
+    settings: Res<MatchSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, mut color) in &mut query {
+        match *interaction {
+            Interaction::Pressed => {
+                if start_spectating(&mut commands, &options, &settings) {
+                    next_state.set(GameState::Connecting);
+                }
+            }
+            Interaction::Hovered => *color = Color::srgb(0.45, 0.45, 0.45).into(),
+            Interaction::None => *color = Color::srgb(0.35, 0.35, 0.35).into(),
+        }
+    }
+}
+
+// Watches the match given with --spectate as soon as the game starts
+pub fn start_launch_spectate(
+    mut commands: Commands,
+    options: Res<LaunchOptions>,
+    settings: Res<MatchSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut launched: Local<bool>,
+) {
+    if *launched || options.spectate.is_none() {
+        return;
+    }
+    *launched = true;
+    if start_spectating(&mut commands, &options, &settings) {
+        next_state.set(GameState::Connecting);
+    }
+}
+
+fn start_spectating(commands: &mut Commands, options: &LaunchOptions, settings: &MatchSettings) -> bool {
+    let address = options.spectate_address();
+    let Some(server) = address.to_socket_addrs().ok().and_then(|mut addresses| addresses.next()) else {
+        error!("Couldn't find match {address}");
+        return false;
+    };
+    let local = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    match NetSession::bind(local, Some(server), Side::Left, options) {
+        Ok(mut session) => {
+            session.spectator = true;
+            commands.insert_resource(session);
+            commands.insert_resource(NetClient::new(settings.clone()));
+            true
+        }
+        Err(error) => {
+            error!("Couldn't open a socket: {error}");
+            false
+        }
+    }
+}
+
+// Puts the freshly set up match into the state the spectator joined at
+pub fn join_spectated_match(world: &mut World) {
+    let Some(snapshot) = world.resource_mut::<NetClient>().join_snapshot.take() else {
+        return;
+    };
+    snapshot::restore(world, &snapshot);
+    world.resource_mut::<NetClient>().follow_from(snapshot.tick);
+}
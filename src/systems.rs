@@ -2,7 +2,12 @@ use bevy::prelude::*;
 use rand::Rng;
 use crate::components::*;
 use crate::resources::*;
-use crate::constants::*;
+use crate::config::GameConfig;
+use crate::physics::*;
+use crate::bindings::{Action, KeyBindings};
+use crate::gamepad::{GamepadAssignments, gamepad_direction};
+use crate::replay::*;
+use crate::net::{NetSession, netcode_label};
 
 // Setup camera - runs once at startup
 pub fn setup_camera(mut commands: Commands) {
@@ -10,15 +15,22 @@ pub fn setup_camera(mut commands: Commands) {
 }
 
 // Start Screen Systems
-pub fn setup_start_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn setup_start_screen(
+    mut commands: Commands,
+    settings: Res<MatchSettings>,
+    options: Res<LaunchOptions>,
+    mouse_control: Res<MouseControl>,
+    bindings: Res<KeyBindings>,
+    config: Res<GameConfig>,
+) {
     // Background
     commands.spawn((
         Sprite {
-            image: asset_server.load("rink.png"),
-            custom_size: Some(Vec2::new(WINDOW_WIDTH, WINDOW_HEIGHT)),
+            custom_size: Some(Vec2::new(config.rink_width, config.rink_height)),
             ..default()
         },
         Transform::from_xyz(0.0, 0.0, -1.0),
+        Rink,
         StartScreenUI,
     ));
 
@@ -51,32 +63,80 @@ pub fn setup_start_screen(mut commands: Commands, asset_server: Res<AssetServer>
             },
         ));
         
-        // Play Button
-        parent.spawn((
-            Button,
-            Node {
-                width: Val::Px(200.0),
-                height: Val::Px(60.0),
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                ..default()
-            },
-            BackgroundColor(Color::srgb(0.2, 0.2, 0.8)),
-            PlayButton,
-        )).with_children(|parent| {
-            parent.spawn((
-                Text::new("PLAY"),
-                TextFont {
-                    font_size: 32.0,
-                    ..default()
-                },
-                TextColor(Color::WHITE),
-            ));
+        // Player count buttons
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(20.0),
+            ..default()
+        }).with_children(|parent| {
+            spawn_menu_button(parent, "1 PLAYER", OnePlayerButton);
+            spawn_menu_button(parent, "2 PLAYERS", TwoPlayerButton);
+        });
+
+        // The most recently saved match in the replay viewer, games on the local
+        // network, the keys and controllers, and the mouse
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            margin: UiRect::top(Val::Px(10.0)),
+            ..default()
+        }).with_children(|parent| {
+            spawn_option_button(parent, "REPLAY VIEWER", Color::srgb(0.35, 0.35, 0.35), WatchReplayButton);
+            spawn_option_button(parent, "LAN LOBBY", Color::srgb(0.35, 0.35, 0.35), LobbyButton);
+            spawn_option_button(parent, "SETTINGS", Color::srgb(0.35, 0.35, 0.35), SettingsButton);
+            spawn_option_button(parent, mouse_control.label(), Color::srgb(0.35, 0.35, 0.35), MouseControlButton);
+        });
+
+        // Two players on separate machines, see --port and --join
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            margin: UiRect::top(Val::Px(10.0)),
+            ..default()
+        }).with_children(|parent| {
+            spawn_option_button(parent, "HOST GAME", Color::srgb(0.35, 0.35, 0.35), HostGameButton);
+            spawn_option_button(parent, "JOIN GAME", Color::srgb(0.35, 0.35, 0.35), JoinGameButton);
+            spawn_option_button(parent, netcode_label(options.rollback), Color::srgb(0.35, 0.35, 0.35), NetcodeButton);
+            spawn_option_button(parent, "SPECTATE", Color::srgb(0.35, 0.35, 0.35), SpectateButton);
+        });
+
+        // CPU difficulty
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            margin: UiRect::top(Val::Px(20.0)),
+            ..default()
+        }).with_children(|parent| {
+            for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+                spawn_option_button(
+                    parent,
+                    difficulty.label(),
+                    difficulty_button_color(difficulty == settings.difficulty, false),
+                    DifficultyButton(difficulty),
+                );
+            }
+        });
+
+        // Match rules, each button cycles through its choices
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            margin: UiRect::top(Val::Px(10.0)),
+            ..default()
+        }).with_children(|parent| {
+            for rule in [Rule::MatchLength, Rule::TargetScore, Rule::WinByTwo, Rule::Physics] {
+                spawn_option_button(
+                    parent,
+                    &settings.rule_label(rule),
+                    Color::srgb(0.35, 0.35, 0.35),
+                    RuleButton(rule),
+                );
+            }
         });
         
         // Controls instruction
         parent.spawn((
-            Text::new("Left Player: W/S\nRight Player: Arrow Keys\nESC: Pause"),
+            Text::new(bindings.help_text()),
             TextFont {
                 font_size: 24.0,
                 ..default()
@@ -90,16 +150,109 @@ pub fn setup_start_screen(mut commands: Commands, asset_server: Res<AssetServer>
     });
 }
 
+fn spawn_menu_button(parent: &mut ChildSpawnerCommands, label: &str, marker: impl Component) {
+    parent.spawn((
+        Button,
+        Node {
+            width: Val::Px(200.0),
+            height: Val::Px(60.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(Color::srgb(0.2, 0.2, 0.8)),
+        marker,
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::new(label),
+            TextFont {
+                font_size: 32.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+    });
+}
+
+pub fn spawn_option_button(parent: &mut ChildSpawnerCommands, label: &str, color: Color, marker: impl Component) {
+    parent.spawn((
+        Button,
+        Node {
+            width: Val::Px(170.0),
+            height: Val::Px(40.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(color),
+        marker,
+    )).with_children(|parent| {
+        parent.spawn((
+            Text::new(label),
+            TextFont {
+                font_size: 20.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+    });
+}
+
+fn difficulty_button_color(selected: bool, hovered: bool) -> Color {
+    match (selected, hovered) {
+        (true, _) => Color::srgb(0.2, 0.6, 0.2),
+        (false, true) => Color::srgb(0.45, 0.45, 0.45),
+        (false, false) => Color::srgb(0.35, 0.35, 0.35),
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub fn handle_start_screen_input(
-    mut interaction_query: Query<
+    mut player_button_query: Query<
+        (&Interaction, &mut BackgroundColor, Has<OnePlayerButton>),
+        (Changed<Interaction>, Or<(With<OnePlayerButton>, With<TwoPlayerButton>)>),
+    >,
+    mut difficulty_button_query: Query<
+        (Ref<Interaction>, &mut BackgroundColor, &DifficultyButton),
+        (Without<OnePlayerButton>, Without<TwoPlayerButton>),
+    >,
+    mut rule_button_query: Query<
+        (&Interaction, &mut BackgroundColor, &RuleButton, &Children),
+        (Changed<Interaction>, Without<OnePlayerButton>, Without<TwoPlayerButton>, Without<DifficultyButton>),
+    >,
+    mut replay_button_query: Query<
         (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<PlayButton>),
+        (Changed<Interaction>, With<WatchReplayButton>, Without<RuleButton>, Without<OnePlayerButton>, Without<TwoPlayerButton>, Without<DifficultyButton>),
     >,
+    mut text_query: Query<&mut Text>,
+    mut settings: ResMut<MatchSettings>,
+    mut config: ResMut<GameConfig>,
     mut next_state: ResMut<NextState<GameState>>,
+    mut commands: Commands,
 ) {
-    for (interaction, mut color) in &mut interaction_query {
+    for (interaction, mut color) in &mut replay_button_query {
+        match *interaction {
+            Interaction::Pressed => match latest_replay() {
+                Some(path) => {
+                    if start_replay(&mut commands, &path, &mut settings, &mut config) {
+                        next_state.set(GameState::ReplayViewer);
+                    }
+                }
+                None => warn!("No replays saved in {REPLAY_DIR} yet"),
+            },
+            Interaction::Hovered => {
+                *color = Color::srgb(0.45, 0.45, 0.45).into();
+            }
+            Interaction::None => {
+                *color = Color::srgb(0.35, 0.35, 0.35).into();
+            }
+        }
+    }
+
+    for (interaction, mut color, one_player) in &mut player_button_query {
         match *interaction {
             Interaction::Pressed => {
+                settings.cpu_side = if one_player { Some(Side::Right) } else { None };
                 next_state.set(GameState::Countdown);
             }
             Interaction::Hovered => {
@@ -110,6 +263,39 @@ pub fn handle_start_screen_input(
             }
         }
     }
+
+    for (interaction, mut color, button, children) in &mut rule_button_query {
+        match *interaction {
+            Interaction::Pressed => {
+                settings.cycle_rule(button.0);
+                // Relabel the button with its new value
+                let mut texts = text_query.iter_many_mut(children);
+                while let Some(mut text) = texts.fetch_next() {
+                    *text = Text::new(settings.rule_label(button.0));
+                }
+            }
+            Interaction::Hovered => {
+                *color = Color::srgb(0.45, 0.45, 0.45).into();
+            }
+            Interaction::None => {
+                *color = Color::srgb(0.35, 0.35, 0.35).into();
+            }
+        }
+    }
+
+    // Select a difficulty first so every button can be recolored in the same pass
+    for (interaction, _, button) in &difficulty_button_query {
+        if interaction.is_changed() && *interaction == Interaction::Pressed {
+            settings.difficulty = button.0;
+        }
+    }
+
+    if settings.is_changed() || difficulty_button_query.iter().any(|(i, _, _)| i.is_changed()) {
+        for (interaction, mut color, button) in &mut difficulty_button_query {
+            let hovered = *interaction == Interaction::Hovered;
+            *color = difficulty_button_color(button.0 == settings.difficulty, hovered).into();
+        }
+    }
 }
 
 pub fn cleanup_start_screen(
@@ -153,7 +339,6 @@ pub fn setup_countdown(mut commands: Commands) {
 }
 
 pub fn update_countdown(
-    commands: Commands,
     mut countdown_timer: ResMut<CountdownTimer>,
     mut query: Query<&mut Text, With<CountdownText>>,
     time: Res<Time>,
@@ -186,52 +371,98 @@ pub fn cleanup_countdown(
 }
 
 // Game Systems
+
+// Only what the simulation needs. The images and meshes are added by add_visuals
+// when there's something to draw them.
 pub fn setup_game(
     mut commands: Commands, 
-    asset_server: Res<AssetServer>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    settings: Res<MatchSettings>,
+    config: Res<GameConfig>,
+    mut fixed_time: ResMut<Time<Fixed>>,
 ) {
+    fixed_time.set_timestep_hz(settings.tick_rate);
+    commands.insert_resource(SimulationTick::default());
+    commands.insert_resource(MatchTime::default());
+    let mut game_rng = GameRng::for_match(&settings);
+    let clock = MatchClock::new(&settings);
+
     // Spawn background
     commands.spawn((
         Sprite {
-            image: asset_server.load("rink.png"),
-            custom_size: Some(Vec2::new(WINDOW_WIDTH, WINDOW_HEIGHT)),
+            custom_size: Some(Vec2::new(config.rink_width, config.rink_height)),
             ..default()
         },
         Transform::from_xyz(0.0, 0.0, -1.0),
+        Rink,
         GameUI,
     ));
     
     // left paddle (circular)
-    commands.spawn((
-        Mesh2d(meshes.add(Circle::new(PADDLE_RADIUS))),
-        MeshMaterial2d(materials.add(Color::srgb(1.0, 0.0, 0.0))), // Red
-        Transform::from_xyz(LEFT_PADDLE_X, 0.0, 0.0),
+    let left_start = config.paddle_start(Side::Left);
+    let left_paddle = commands.spawn((
+        Transform::from_translation(left_start.extend(0.0)),
         Paddle { side: Side::Left },
+        Position(left_start),
+        PreviousPosition(left_start),
+        Velocity(Vec2::ZERO),
+        PaddleInput::default(),
         GameUI,
-    ));
+    )).id();
 
     // right paddle (circular)
-    commands.spawn((
-        Mesh2d(meshes.add(Circle::new(PADDLE_RADIUS))),
-        MeshMaterial2d(materials.add(Color::srgb(0.0, 0.0, 1.0))), // Blue
-        Transform::from_xyz(RIGHT_PADDLE_X, 0.0, 0.0),
+    let right_start = config.paddle_start(Side::Right);
+    let right_paddle = commands.spawn((
+        Transform::from_translation(right_start.extend(0.0)),
         Paddle { side: Side::Right },
+        Position(right_start),
+        PreviousPosition(right_start),
+        Velocity(Vec2::ZERO),
+        PaddleInput::default(),
         GameUI,
-    ));
+    )).id();
+
+    // Hand one paddle to the CPU in single-player matches
+    if let Some(cpu_side) = settings.cpu_side {
+        let cpu_paddle = match cpu_side {
+            Side::Left => left_paddle,
+            Side::Right => right_paddle,
+        };
+        commands.entity(cpu_paddle).insert(CpuPaddle {
+            reaction_timer: Timer::from_seconds(settings.difficulty.reaction_delay(), TimerMode::Repeating),
+            target: Vec2::ZERO,
+        });
+    }
+
+    // goals, drawn over the back edge of the rink
+    for end in [-1.0, 1.0] {
+        let goal_x = end * config.rink_width / 2.0;
+        commands.spawn((
+            Transform::from_xyz(goal_x, 0.0, -0.5),
+            Goal(end),
+            GameUI,
+        ));
+
+        for post_side in [-1.0, 1.0] {
+            commands.spawn((
+                Transform::from_xyz(goal_x, post_side * config.goal_width / 2.0, -0.4),
+                GoalPost(Vec2::new(end, post_side)),
+                GameUI,
+            ));
+        }
+    }
 
     // puck
-    let initial_direction = if rand::thread_rng().gen_bool(0.5) { 1.0 } else { -1.0 };
+    let initial_direction = if game_rng.rng.gen_bool(0.5) { 1.0 } else { -1.0 };
     commands.spawn((
         Sprite {
-            image: asset_server.load("puck.png"),
-            custom_size: Some(Vec2::new(PUCK_SIZE, PUCK_SIZE)),
+            custom_size: Some(Vec2::splat(config.puck_size)),
             ..default()
         },
         Transform::from_xyz(0.0, 0.0, 0.0),
         Puck,
-        Velocity(Vec2::new(PUCK_SPEED * initial_direction, 0.0)),
+        Position(Vec2::ZERO),
+        PreviousPosition(Vec2::ZERO),
+        Velocity(Vec2::new(config.puck_speed * initial_direction, 0.0)),
         GameUI,
     ));
 
@@ -250,20 +481,34 @@ pub fn setup_game(
         },
         GameUI,
     )).with_children(|parent| {
-        // Score display
-        parent.spawn((
-            Text::new("0 - 0"),
-            TextFont {
-                font_size: 48.0,
-                ..default()
-            },
-            TextColor(Color::BLACK),
-            Node {
-                margin: UiRect::top(Val::Px(20.0)),
-                ..default()
-            },
-            ScoreText,
-        ));
+        // Score and match clock
+        parent.spawn(Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(30.0),
+            margin: UiRect::top(Val::Px(20.0)),
+            ..default()
+        }).with_children(|parent| {
+            parent.spawn((
+                Text::new("0 - 0"),
+                TextFont {
+                    font_size: 48.0,
+                    ..default()
+                },
+                TextColor(Color::BLACK),
+                ScoreText,
+            ));
+
+            parent.spawn((
+                Text::new(clock_label(&clock)),
+                TextFont {
+                    font_size: 36.0,
+                    ..default()
+                },
+                TextColor(Color::BLACK),
+                ClockText,
+            ));
+        });
         
         // Pause display
         parent.spawn((
@@ -279,165 +524,364 @@ pub fn setup_game(
             },
             PauseText,
         ));
+
+        // Shown while watching a replay
+        parent.spawn((
+            Text::new(""),
+            TextFont {
+                font_size: 24.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.8, 0.1, 0.1)),
+            ReplayText,
+        ));
     });
+
+    commands.insert_resource(clock);
+    commands.insert_resource(game_rng);
 }
 
 pub fn handle_pause_input(
     keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    gamepads: Query<&Gamepad>,
     mut paused: ResMut<GamePaused>,
-    mut timer: ResMut<PuckDelayTimer>,
-    time: Res<Time>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::Escape) {
+    if bindings.just_pressed(&keyboard_input, Action::Pause)
+        || gamepads.iter().any(|gamepad| gamepad.just_pressed(GamepadButton::Start))
+    {
         paused.0 = !paused.0;
     }
-    
-    // Only tick the timer when the game is not paused
-    if !paused.0 {
-        timer.0.tick(time.delta());
+}
+
+// Simulation Systems - these run on the fixed timestep so a match plays out the
+// same whatever the frame rate
+
+// Run condition for the simulation: a live match or the replay viewer, unpaused
+// and not waiting on a lost connection
+pub fn simulation_running(
+    state: Res<State<GameState>>,
+    paused: Res<GamePaused>,
+    session: Option<Res<NetSession>>,
+) -> bool {
+    matches!(state.get(), GameState::Playing | GameState::ReplayViewer)
+        && !paused.0
+        && !session.is_some_and(|session| session.lost)
+}
+
+// Remember where everything was before this tick so rendering can interpolate
+pub fn store_previous_positions(mut query: Query<(&Position, &mut PreviousPosition)>) {
+    for (position, mut previous) in &mut query {
+        previous.0 = position.0;
     }
 }
 
-pub fn move_paddles(
+// Last in every tick, so the systems in a tick all see the same tick number
+pub fn advance_tick(mut tick: ResMut<SimulationTick>) {
+    tick.0 += 1;
+}
+
+pub fn tick_serve_delay(mut timer: ResMut<PuckDelayTimer>, time: Res<Time>) {
+    timer.0.tick(time.delta());
+}
+
+pub fn tick_match_time(mut match_time: ResMut<MatchTime>, time: Res<Time>) {
+    match_time.0 += time.delta_secs();
+}
+
+// Samples the keyboard and controllers once per frame, before the fixed ticks
+// that consume it
+#[allow(clippy::type_complexity)]
+pub fn read_paddle_input(
     keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut query: Query<(&mut Transform, &Paddle)>,
-    time: Res<Time>,
+    bindings: Res<KeyBindings>,
+    gamepads: Query<&Gamepad>,
+    assignments: Res<GamepadAssignments>,
+    mut query: Query<(&mut PaddleInput, &Paddle), (Without<CpuPaddle>, Without<RemotePaddle>, Without<MousePaddle>)>,
 ) {
-    for (mut transform, paddle) in &mut query {
-        let mut direction = 0.0;
+    // With only one player at this machine, either side's controller will do
+    let shared = query.iter().count() == 1;
+    for (mut input, paddle) in &mut query {
+        let mut direction = Vec2::ZERO;
 
-        // Controls
-        if paddle.side == Side::Left {
-            if keyboard_input.pressed(KeyCode::KeyW) {
-                direction += 1.0;
-            }
-            if keyboard_input.pressed(KeyCode::KeyS) {
-                direction -= 1.0;
-            }
+        // Controls, see KeyBindings
+        let [up, down, left, right] = Action::movement(paddle.side);
+        if bindings.pressed(&keyboard_input, up) {
+            direction.y += 1.0;
+        }
+        if bindings.pressed(&keyboard_input, down) {
+            direction.y -= 1.0;
+        }
+        if bindings.pressed(&keyboard_input, left) {
+            direction.x -= 1.0;
+        }
+        if bindings.pressed(&keyboard_input, right) {
+            direction.x += 1.0;
+        }
+
+        // Diagonals are no faster than straight moves
+        direction = direction.normalize_or_zero();
+
+        // Half way on the stick is half speed
+        let controllers = if shared {
+            [assignments.left, assignments.right]
         } else {
-            if keyboard_input.pressed(KeyCode::ArrowUp) {
-                direction += 1.0;
-            }
-            if keyboard_input.pressed(KeyCode::ArrowDown) {
-                direction -= 1.0;
-            }
+            [assignments.get(paddle.side), None]
+        };
+        for gamepad in gamepads.iter_many(controllers.into_iter().flatten()) {
+            direction += gamepad_direction(gamepad);
         }
+        input.0 = direction.clamp_length_max(1.0);
+    }
+}
+
+pub fn move_paddles(
+    mut query: Query<(&mut Position, &mut Velocity, &PaddleInput, &Paddle)>,
+    config: Res<GameConfig>,
+    time: Res<Time>,
+) {
+    for (mut position, mut velocity, input, paddle) in &mut query {
+        let target = step_paddle(paddle.side, position.0, input.0, time.delta_secs(), &config);
+        velocity.0 = paddle_velocity(position.0, target, time.delta_secs());
+        position.0 = target;
+    }
+}
 
-        transform.translation.y += direction * PADDLE_SPEED * time.delta_secs();
+// Where a paddle ends up after one tick of input. Networked clients use this to
+// predict their own paddle exactly as the host will move it.
+pub fn step_paddle(side: Side, position: Vec2, input: Vec2, delta_secs: f32, config: &GameConfig) -> Vec2 {
+    let movement = input.clamp_length_max(1.0) * config.paddle_speed * delta_secs;
+    clamp_paddle(side, position + movement, config)
+}
 
-        // Keep paddles inside window (using radius for circular paddles)
-        let half_height = WINDOW_HEIGHT / 2.0 - PADDLE_RADIUS;
-        transform.translation.y = transform.translation.y.clamp(-half_height, half_height);
+// How fast a paddle actually moved this tick, after clamping
+pub fn paddle_velocity(from: Vec2, to: Vec2, delta_secs: f32) -> Vec2 {
+    if delta_secs > 0.0 {
+        (to - from) / delta_secs
+    } else {
+        Vec2::ZERO
     }
 }
 
+// Keeps a paddle inside the window and on its own side of the center line
+pub fn clamp_paddle(side: Side, position: Vec2, config: &GameConfig) -> Vec2 {
+    let half_width = config.rink_width / 2.0 - config.paddle_radius;
+    let half_height = config.rink_height / 2.0 - config.paddle_radius;
+    let center_limit = config.center_line_margin + config.paddle_radius;
+    let (min_x, max_x) = match side {
+        Side::Left => (-half_width, -center_limit),
+        Side::Right => (center_limit, half_width),
+    };
+    Vec2::new(
+        position.x.clamp(min_x, max_x),
+        position.y.clamp(-half_height, half_height),
+    )
+}
+
 pub fn move_puck(
-    mut query: Query<(&mut Transform, &mut Velocity), With<Puck>>,
+    mut query: Query<(&mut Position, &mut Velocity), With<Puck>>,
     time: Res<Time>,
     timer: Res<PuckDelayTimer>,
+    settings: Res<MatchSettings>,
+    config: Res<GameConfig>,
+    mut time_used: ResMut<PuckTimeUsed>,
 ) {
     // Only move puck if delay is finished
     if !timer.0.is_finished() {
         return;
     }
 
-    for (mut transform, mut velocity) in &mut query {
-        transform.translation += velocity.0.extend(0.0) * time.delta_secs();
+    // A puck that met a paddle part way through the tick only has the rest of it left
+    let remaining = (time.delta_secs() - time_used.0).max(0.0);
+    time_used.0 = 0.0;
 
-        // Bounce off top and bottom with position correction
-        let boundary = WINDOW_HEIGHT / 2.0 - PUCK_SIZE / 2.0;
-        if transform.translation.y > boundary {
-            // Hit top wall
-            transform.translation.y = boundary; // Correct position
-            velocity.0.y = -velocity.0.y.abs(); // Ensure velocity points downward
-        } else if transform.translation.y < -boundary {
-            // Hit bottom wall
-            transform.translation.y = -boundary; // Correct position
-            velocity.0.y = velocity.0.y.abs(); // Ensure velocity points upward
+    let physics = settings.physics;
+    for (mut position, mut velocity) in &mut query {
+        // Air-cushion drag slowly bleeds off speed until the puck comes to rest,
+        // over only the part of the tick the puck still has to move
+        velocity.0 *= (-physics.linear_drag(&config) * remaining).exp();
+        if physics != PhysicsMode::Classic && velocity.0.length() < config.puck_rest_speed {
+            velocity.0 = Vec2::ZERO;
         }
+
+        // Walls are swept rather than checked after moving, so a long tick can't
+        // carry the puck through one
+        advance_puck(&mut position.0, &mut velocity.0, remaining, physics.wall_restitution(&config), &config);
     }
 }
 
+// Runs after the paddles have moved but before the puck does. Each paddle's path
+// this tick is rebuilt from its velocity and swept against the puck's upcoming
+// path, so fast paddles can't skip over a hit.
+#[allow(clippy::type_complexity)]
 pub fn check_paddle_collision(
-    mut puck_query: Query<(&Transform, &mut Velocity), With<Puck>>,
-    paddle_query: Query<&Transform, (With<Paddle>, Without<Puck>)>,
+    mut puck_query: Query<(&mut Position, &mut Velocity), With<Puck>>,
+    paddle_query: Query<(&Position, &Velocity), (With<Paddle>, Without<Puck>)>,
     timer: Res<PuckDelayTimer>,
+    settings: Res<MatchSettings>,
+    config: Res<GameConfig>,
+    time: Res<Time>,
+    mut time_used: ResMut<PuckTimeUsed>,
 ) {
     // Only check collisions if the delay timer has finished
     if !timer.0.is_finished() {
         return;
     }
 
-    if let Ok((puck_transform, mut puck_velocity)) = puck_query.single_mut() {
-        for paddle_transform in &paddle_query {
-            let paddle_pos = paddle_transform.translation;
-            let puck_pos = puck_transform.translation;
+    let delta = time.delta_secs();
+    let collision_distance = config.paddle_radius + config.puck_size / 2.0;
 
-            // Calculate distance between centers (circle-circle collision)
-            let distance = puck_pos.xy().distance(paddle_pos.xy());
-            let collision_distance = PADDLE_RADIUS + PUCK_SIZE / 2.0;
+    if let Ok((mut puck_position, mut puck_velocity)) = puck_query.single_mut() {
+        let puck_pos = puck_position.0;
 
-            if distance < collision_distance && distance > 0.0 {
-                // Calculate collision normal (direction from paddle center to puck center)
-                let collision_normal = (puck_pos.xy() - paddle_pos.xy()).normalize();
-                
-                // Calculate relative velocity in collision normal direction
-                let velocity_along_normal = puck_velocity.0.dot(collision_normal);
-                
-                // Only resolve collision if objects are moving toward each other
-                if velocity_along_normal < 0.0 {
-                    // Reflect velocity along the collision normal (realistic circular collision)
-                    puck_velocity.0 -= 2.0 * velocity_along_normal * collision_normal;
-                    
-                    // Maintain constant speed (like real air hockey)
-                    let current_speed = puck_velocity.0.length();
-                    if current_speed > 0.0 {
-                        puck_velocity.0 = puck_velocity.0.normalize() * PUCK_SPEED;
-                    }
-                }
+        // Paddles already overlapping the puck (one moved into a resting puck) just push it out
+        for (paddle_position, paddle_velocity) in &paddle_query {
+            let paddle_pos = paddle_position.0;
+            let distance = puck_pos.distance(paddle_pos);
+            let paddle_start = paddle_pos - paddle_velocity.0 * delta;
+            let overlapping_at_start = puck_pos.distance(paddle_start) < collision_distance;
+
+            if distance < collision_distance && distance > 0.0 && overlapping_at_start {
+                let collision_normal = (puck_pos - paddle_pos) / distance;
+                puck_position.0 = paddle_pos + collision_normal * collision_distance;
+                puck_velocity.0 = paddle_hit(puck_velocity.0, paddle_velocity.0, collision_normal, settings.physics, &config);
+                return;
+            }
+        }
+
+        // Otherwise find the first paddle the puck meets this tick
+        let mut first_hit: Option<(f32, Vec2, Vec2)> = None;
+        for (paddle_position, paddle_velocity) in &paddle_query {
+            let paddle_start = paddle_position.0 - paddle_velocity.0 * delta;
+            let relative_motion = (puck_velocity.0 - paddle_velocity.0) * delta;
+            if let Some(fraction) = sweep_circles(puck_pos - paddle_start, relative_motion, collision_distance)
+                && first_hit.is_none_or(|(first, _, _)| fraction < first)
+            {
+                first_hit = Some((fraction, paddle_start, paddle_velocity.0));
             }
         }
+
+        if let Some((fraction, paddle_start, paddle_velocity)) = first_hit {
+            let time_of_impact = fraction * delta;
+            let puck_contact = puck_pos + puck_velocity.0 * time_of_impact;
+            let paddle_contact = paddle_start + paddle_velocity * time_of_impact;
+            let collision_normal = (puck_contact - paddle_contact).normalize_or_zero();
+
+            puck_velocity.0 = paddle_hit(puck_velocity.0, paddle_velocity, collision_normal, settings.physics, &config);
+
+            // The puck stays where it met the paddle. Stepping it back along its
+            // new path instead could put it behind a wall or goal post it never reached.
+            puck_position.0 = puck_contact;
+            time_used.0 = time_of_impact;
+        }
     }
 }
 
 pub fn check_score(
-    mut puck_query: Query<&mut Transform, With<Puck>>,
-    mut velocity_query: Query<&mut Velocity, With<Puck>>,
-    mut paddle_query: Query<(&mut Transform, &Paddle), Without<Puck>>,
+    mut puck_query: Query<(&mut Position, &mut PreviousPosition, &mut Velocity), With<Puck>>,
+    mut paddle_query: Query<(&mut Position, &mut PreviousPosition, &Paddle), Without<Puck>>,
     mut score: ResMut<Score>,
     mut timer: ResMut<PuckDelayTimer>,
+    mut goals: MessageWriter<GoalScored>,
+    config: Res<GameConfig>,
 ) {
-    if let (Ok(mut puck_transform), Ok(mut velocity)) = (puck_query.single_mut(), velocity_query.single_mut()) {
+    if let Ok((mut puck_position, mut puck_previous, mut velocity)) = puck_query.single_mut() {
         let mut scored = false;
 
-        if puck_transform.translation.x < -WINDOW_WIDTH / 2.0 - 50.0 {
+        // A goal counts once the whole puck is over the end line inside the goal mouth
+        let goal_line = config.rink_width / 2.0 + config.puck_size / 2.0;
+        if puck_position.0.x < -goal_line {
             score.right += 1;
             scored = true;
-            velocity.0 = Vec2::new(PUCK_SPEED, 0.0); // Right player scored, shoot left
-        } else if puck_transform.translation.x > WINDOW_WIDTH / 2.0 + 50.0 {
+            goals.write(GoalScored { side: Side::Right });
+            velocity.0 = Vec2::new(config.puck_speed, 0.0); // Right player scored, shoot left
+        } else if puck_position.0.x > goal_line {
             score.left += 1;
             scored = true;
-            velocity.0 = Vec2::new(-PUCK_SPEED, 0.0); // Left player scored, shoot right
+            goals.write(GoalScored { side: Side::Left });
+            velocity.0 = Vec2::new(-config.puck_speed, 0.0); // Left player scored, shoot right
         }
 
         if scored {
-            // Reset puck position to center
-            puck_transform.translation = Vec3::ZERO;
+            // Reset puck position to center. The previous position moves too so
+            // the reset isn't drawn as a streak across the rink.
+            puck_position.0 = Vec2::ZERO;
+            puck_previous.0 = Vec2::ZERO;
 
             // Reset paddles to center position
-            for (mut paddle_transform, paddle) in &mut paddle_query {
-                match paddle.side {
-                    Side::Left => {
-                        paddle_transform.translation = Vec3::new(LEFT_PADDLE_X, 0.0, 0.0);
-                    }
-                    Side::Right => {
-                        paddle_transform.translation = Vec3::new(RIGHT_PADDLE_X, 0.0, 0.0);
-                    }
-                }
+            for (mut paddle_position, mut paddle_previous, paddle) in &mut paddle_query {
+                paddle_position.0 = config.paddle_start(paddle.side);
+                paddle_previous.0 = paddle_position.0;
             }
 
-            // Start 2 second timer
-            timer.0 = Timer::from_seconds(2.0, TimerMode::Once);
+            // Hold the puck for the serve delay
+            timer.0 = Timer::from_seconds(config.serve_delay, TimerMode::Once);
+        }
+    }
+}
+
+// Only in a live match. The replay viewer keeps simulating right to the end.
+pub fn end_match_when_won(
+    score: Res<Score>,
+    settings: Res<MatchSettings>,
+    clock: Res<MatchClock>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if settings.winner(&score, &clock).is_some() {
+        next_state.set(GameState::GameOver);
+    }
+}
+
+// Rendering Systems
+
+// Draws the puck and paddles part of the way between their last two simulated
+// positions, so motion stays smooth when the frame rate and tick rate differ
+pub fn interpolate_transforms(
+    mut query: Query<(&mut Transform, &Position, &PreviousPosition)>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let alpha = fixed_time.overstep_fraction();
+    for (mut transform, position, previous) in &mut query {
+        let drawn = previous.0.lerp(position.0, alpha);
+        transform.translation = drawn.extend(transform.translation.z);
+    }
+}
+
+fn clock_label(clock: &MatchClock) -> String {
+    match clock.remaining {
+        _ if clock.overtime => "OVERTIME".to_string(),
+        Some(remaining) => format_clock(remaining),
+        None => String::new(),
+    }
+}
+
+// Runs the timed-match clock, which stops during the serve delay
+pub fn update_match_clock(
+    mut clock: ResMut<MatchClock>,
+    timer: Res<PuckDelayTimer>,
+    time: Res<Time>,
+) {
+    if clock.overtime || !timer.0.is_finished() {
+        return;
+    }
+    let Some(remaining) = clock.remaining.as_mut() else {
+        return;
+    };
+
+    *remaining = (*remaining - time.delta_secs()).max(0.0);
+    if *remaining == 0.0 {
+        // Tied at the buzzer goes to sudden death, otherwise end_match_when_won ends it
+        clock.overtime = true;
+    }
+}
+
+pub fn update_clock_display(
+    clock: Res<MatchClock>,
+    mut query: Query<&mut Text, With<ClockText>>,
+) {
+    if clock.is_changed() {
+        for mut text in &mut query {
+            *text = Text::new(clock_label(&clock));
         }
     }
 }
@@ -455,6 +899,8 @@ pub fn update_score_display(
 
 pub fn update_pause_display(
     paused: Res<GamePaused>,
+    game_rng: Res<GameRng>,
+    session: Option<Res<NetSession>>,
     mut commands: Commands,
     pause_ui_query: Query<Entity, With<PauseScreenUI>>,
     mut text_query: Query<&mut Text, With<PauseText>>,
@@ -491,7 +937,21 @@ pub fn update_pause_display(
                     },
                     TextColor(Color::WHITE),
                     Node {
-                        margin: UiRect::bottom(Val::Px(40.0)),
+                        margin: UiRect::bottom(Val::Px(10.0)),
+                        ..default()
+                    },
+                ));
+
+                // Seed, so an interesting match can be played again with --seed
+                parent.spawn((
+                    Text::new(format!("SEED: {}", game_rng.seed)),
+                    TextFont {
+                        font_size: 20.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.8, 0.8, 0.8)),
+                    Node {
+                        margin: UiRect::bottom(Val::Px(30.0)),
                         ..default()
                     },
                 ));
@@ -520,8 +980,9 @@ pub fn update_pause_display(
                     ));
                 });
                 
-                // Restart Button
-                parent.spawn((
+                // Restart Button, or a way out of a networked match which can't be
+                // restarted from one side
+                let mut button = parent.spawn((
                     Button,
                     Node {
                         width: Val::Px(200.0),
@@ -531,10 +992,17 @@ pub fn update_pause_display(
                         ..default()
                     },
                     BackgroundColor(Color::srgb(0.8, 0.2, 0.2)),
-                    RestartButton,
-                )).with_children(|parent| {
+                ));
+                let label = if session.is_some() {
+                    button.insert(LeaveMatchButton);
+                    "MAIN MENU"
+                } else {
+                    button.insert(RestartButton);
+                    "RESTART"
+                };
+                button.with_children(|parent| {
                     parent.spawn((
-                        Text::new("RESTART"),
+                        Text::new(label),
                         TextFont {
                             font_size: 24.0,
                             ..default()
@@ -552,6 +1020,7 @@ pub fn update_pause_display(
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub fn handle_pause_screen_input(
     mut resume_button_query: Query<
         (&Interaction, &mut BackgroundColor),
@@ -588,22 +1057,7 @@ pub fn handle_pause_screen_input(
     for (interaction, mut color) in &mut restart_button_query {
         match *interaction {
             Interaction::Pressed => {
-                // Reset score
-                score.left = 0;
-                score.right = 0;
-                
-                // Clean up game entities
-                for entity in &game_entities {
-                    commands.entity(entity).despawn();
-                }
-                
-                // Clean up pause screen entities
-                for entity in &pause_ui_query {
-                    commands.entity(entity).despawn();
-                }
-                
-                // Reset puck delay timer to add delay at start
-                puck_timer.0 = Timer::from_seconds(2.0, TimerMode::Once);
+                reset_match(&mut commands, game_entities.iter().chain(&pause_ui_query), &mut score, &mut puck_timer);
                 
                 // Unpause and go to countdown
                 paused.0 = false;
@@ -617,4 +1071,343 @@ pub fn handle_pause_screen_input(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// Clears everything from the current match so a new one can be set up
+pub fn reset_match(
+    commands: &mut Commands,
+    entities: impl IntoIterator<Item = Entity>,
+    score: &mut Score,
+    puck_timer: &mut PuckDelayTimer,
+) {
+    // Reset score
+    score.left = 0;
+    score.right = 0;
+
+    // Clean up game, pause and results screen entities
+    for entity in entities {
+        commands.entity(entity).despawn();
+    }
+
+    // Reset puck delay timer to add delay at start. It's already as long as the
+    // config's serve delay.
+    puck_timer.0.reset();
+}
+
+pub fn format_clock(seconds: f32) -> String {
+    let seconds = seconds.max(0.0).ceil() as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// Game Over Systems
+pub fn setup_game_over_screen(
+    mut commands: Commands,
+    score: Res<Score>,
+    settings: Res<MatchSettings>,
+    clock: Res<MatchClock>,
+    match_time: Res<MatchTime>,
+    session: Option<Res<NetSession>>,
+) {
+    let spectator = session.is_some_and(|session| session.spectator);
+    let winner = settings.winner(&score, &clock);
+    let headline = match (winner, settings.cpu_side) {
+        (Some(side), Some(cpu_side)) if side == cpu_side => "CPU WINS",
+        (Some(Side::Left), _) if spectator => "RED WINS!",
+        (Some(Side::Right), _) if spectator => "BLUE WINS!",
+        (Some(_), Some(_)) => "YOU WIN!",
+        (Some(Side::Left), None) => "RED WINS!",
+        (Some(Side::Right), None) => "BLUE WINS!",
+        (None, _) => "GAME OVER",
+    };
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            top: Val::Px(0.0),
+            left: Val::Px(0.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+        GameOverUI,
+    )).with_children(|parent| {
+        // Winner
+        parent.spawn((
+            Text::new(headline),
+            TextFont {
+                font_size: 64.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            Node {
+                margin: UiRect::bottom(Val::Px(20.0)),
+                ..default()
+            },
+        ));
+
+        // Final score and match length
+        parent.spawn((
+            Text::new(format!(
+                "Final Score: {} - {}\nMatch Length: {}",
+                score.left,
+                score.right,
+                format_clock(match_time.0),
+            )),
+            TextFont {
+                font_size: 28.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            TextLayout::new_with_justify(Justify::Center),
+            Node {
+                margin: UiRect::bottom(Val::Px(40.0)),
+                ..default()
+            },
+        ));
+
+        // Rematch Button. Spectators wait for whatever the players do next.
+        if !spectator {
+            parent.spawn((
+                Button,
+                Node {
+                    width: Val::Px(200.0),
+                    height: Val::Px(60.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    margin: UiRect::bottom(Val::Px(20.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgb(0.2, 0.6, 0.2)),
+                RematchButton,
+            )).with_children(|parent| {
+                parent.spawn((
+                    Text::new("REMATCH"),
+                    TextFont {
+                        font_size: 24.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                ));
+            });
+        }
+
+        // Main Menu Button
+        parent.spawn((
+            Button,
+            Node {
+                width: Val::Px(200.0),
+                height: Val::Px(60.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.2, 0.2, 0.8)),
+            MainMenuButton,
+        )).with_children(|parent| {
+            parent.spawn((
+                Text::new("MAIN MENU"),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+        });
+    });
+}
+
+#[allow(clippy::type_complexity)]
+pub fn handle_game_over_input(
+    mut rematch_button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<RematchButton>),
+    >,
+    mut menu_button_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MainMenuButton>, Without<RematchButton>),
+    >,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut commands: Commands,
+    game_entities: Query<Entity, Or<(With<GameUI>, With<GameOverUI>)>>,
+    mut score: ResMut<Score>,
+    mut puck_timer: ResMut<PuckDelayTimer>,
+) {
+    // Handle Rematch Button
+    for (interaction, mut color) in &mut rematch_button_query {
+        match *interaction {
+            Interaction::Pressed => {
+                reset_match(&mut commands, game_entities.iter(), &mut score, &mut puck_timer);
+                next_state.set(GameState::Countdown);
+            }
+            Interaction::Hovered => {
+                *color = Color::srgb(0.25, 0.65, 0.25).into();
+            }
+            Interaction::None => {
+                *color = Color::srgb(0.2, 0.6, 0.2).into();
+            }
+        }
+    }
+
+    // Handle Main Menu Button
+    for (interaction, mut color) in &mut menu_button_query {
+        match *interaction {
+            Interaction::Pressed => {
+                reset_match(&mut commands, game_entities.iter(), &mut score, &mut puck_timer);
+                next_state.set(GameState::StartScreen);
+            }
+            Interaction::Hovered => {
+                *color = Color::srgb(0.25, 0.25, 0.85).into();
+            }
+            Interaction::None => {
+                *color = Color::srgb(0.2, 0.2, 0.8).into();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::ecs::system::RunSystemOnce;
+    use super::*;
+
+    // Five ticks a second carries a puck at full speed further in one tick
+    // than the paddle and puck are across, so only a swept check sees the hit
+    const LONG_TICK: f32 = 0.2;
+
+    // Just what the collision systems read, with the serve delay already over
+    fn world() -> World {
+        let mut world = World::new();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(LONG_TICK));
+        world.insert_resource(time);
+        let mut timer = Timer::from_seconds(1.0, TimerMode::Once);
+        timer.tick(Duration::from_secs(1));
+        world.insert_resource(PuckDelayTimer(timer));
+        world.insert_resource(MatchSettings::default());
+        world.insert_resource(GameConfig::default());
+        world.insert_resource(PuckTimeUsed::default());
+        world
+    }
+
+    fn spawn_puck(world: &mut World, position: Vec2, velocity: Vec2) {
+        world.spawn((Position(position), Velocity(velocity), Puck));
+    }
+
+    // Velocity is how far the paddle came this tick, it ends up at position
+    fn spawn_paddle(world: &mut World, position: Vec2, velocity: Vec2) {
+        world.spawn((Position(position), Velocity(velocity), Paddle { side: Side::Left }));
+    }
+
+    // One tick of the puck, in the order the game runs it
+    fn step(world: &mut World) -> (Vec2, Vec2) {
+        world.run_system_once(check_paddle_collision).unwrap();
+        world.run_system_once(move_puck).unwrap();
+        let (position, velocity) = world.query_filtered::<(&Position, &Velocity), With<Puck>>().single(world).unwrap();
+        (position.0, velocity.0)
+    }
+
+    #[test]
+    fn fast_puck_cannot_pass_through_a_paddle() {
+        let mut world = world();
+        // Tall enough that the rebound doesn't reach the wall within the tick
+        let config = GameConfig { rink_height: 1000.0, ..default() };
+        world.insert_resource(config.clone());
+        spawn_paddle(&mut world, Vec2::new(-200.0, 0.0), Vec2::ZERO);
+        spawn_puck(&mut world, Vec2::new(-200.0, 150.0), Vec2::new(0.0, -config.puck_max_speed));
+
+        let (position, velocity) = step(&mut world);
+        assert!(velocity.y > 0.0, "puck went through, moving at {velocity}");
+        assert!(position.y > 0.0);
+    }
+
+    // Sent back off a paddle guarding the goal, the puck must not end up behind
+    // the goal post and bounce off the back of it into its own goal
+    #[test]
+    fn puck_off_a_paddle_stays_in_front_of_the_goal() {
+        let mut world = world();
+        world.resource_mut::<MatchSettings>().physics = PhysicsMode::Realistic;
+        let config = GameConfig::default();
+        let post = Vec2::new(-config.rink_width / 2.0, config.goal_width / 2.0);
+        spawn_paddle(&mut world, Vec2::new(post.x + config.paddle_radius, post.y), Vec2::ZERO);
+        spawn_puck(&mut world, Vec2::new(-100.0, post.y), Vec2::new(-config.puck_max_speed, 0.0));
+
+        let (position, velocity) = step(&mut world);
+        assert!(velocity.x > 0.0, "puck sent back into the goal, moving at {velocity}");
+        assert!(position.x > post.x + config.paddle_radius);
+    }
+
+    #[test]
+    fn fast_paddle_cannot_pass_through_the_puck() {
+        let mut world = world();
+        spawn_paddle(&mut world, Vec2::new(-50.0, 0.0), Vec2::new(270.0 / LONG_TICK, 0.0));
+        spawn_puck(&mut world, Vec2::new(-200.0, 0.0), Vec2::ZERO);
+
+        let (_, velocity) = step(&mut world);
+        assert!(velocity.x > 0.0, "paddle went through the resting puck");
+    }
+
+    // Speed of a puck coming in at 300 once a paddle moving at paddle_velocity
+    // meets it head on, in realistic physics
+    fn rebound_speed(paddle_velocity: f32, config: GameConfig) -> f32 {
+        let mut world = world();
+        world.resource_mut::<MatchSettings>().physics = PhysicsMode::Realistic;
+        world.insert_resource(config);
+        spawn_paddle(&mut world, Vec2::new(-200.0, 0.0), Vec2::new(paddle_velocity, 0.0));
+        spawn_puck(&mut world, Vec2::new(-100.0, 0.0), Vec2::new(-300.0, 0.0));
+
+        world.run_system_once(check_paddle_collision).unwrap();
+        let velocity = world.query_filtered::<&Velocity, With<Puck>>().single(&world).unwrap();
+        assert!(velocity.0.x > 0.0, "puck not sent back, moving at {}", velocity.0);
+        velocity.0.length()
+    }
+
+    #[test]
+    fn paddle_hits_transfer_momentum_up_to_the_max_speed() {
+        let config = GameConfig::default();
+        let still = rebound_speed(0.0, config.clone());
+        assert!((still - 300.0 * config.paddle_restitution).abs() < 0.01, "still paddle sent it off at {still}");
+
+        let swung = rebound_speed(200.0, config.clone());
+        assert!(swung > still + 100.0, "a swing at 200 only sent it off at {swung}");
+
+        let softer = rebound_speed(0.0, GameConfig { paddle_restitution: 0.5, ..config.clone() });
+        assert!((softer - 150.0).abs() < 0.01, "restitution of 0.5 sent it off at {softer}");
+
+        let capped = rebound_speed(5000.0, config.clone());
+        assert!((capped - config.puck_max_speed).abs() < 0.01, "a huge swing sent it off at {capped}");
+    }
+
+    #[test]
+    fn fast_puck_cannot_pass_through_a_wall() {
+        let mut world = world();
+        let config = GameConfig::default();
+        spawn_puck(&mut world, Vec2::ZERO, Vec2::new(100.0, config.puck_max_speed));
+
+        let boundary = config.rink_height / 2.0 - config.puck_size / 2.0;
+        let (position, velocity) = step(&mut world);
+        assert!(velocity.y < 0.0, "puck went through, moving at {velocity}");
+        assert!(position.y <= boundary);
+
+        // Still inside after bouncing back and forth a few more times
+        for _ in 0..5 {
+            assert!(step(&mut world).0.y.abs() <= boundary);
+        }
+    }
+
+    #[test]
+    fn fast_puck_bounces_off_a_goal_post() {
+        let mut world = world();
+        let config = GameConfig::default();
+        let post = Vec2::new(config.rink_width / 2.0, config.goal_width / 2.0);
+        spawn_puck(&mut world, post - Vec2::new(250.0, 0.0), Vec2::new(config.puck_max_speed, 0.0));
+
+        let (position, velocity) = step(&mut world);
+        assert!(velocity.x < 0.0, "puck went through, moving at {velocity}");
+        assert!(position.distance(post) >= config.puck_size / 2.0 + config.goal_post_radius - 0.01);
+    }
+}
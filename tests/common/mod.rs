@@ -0,0 +1,157 @@
+// Shared setup for the integration tests. Each test gets its own headless app
+// with a manual clock that moves one simulation tick per update.
+
+#![allow(dead_code)]
+
+use std::time::Duration;
+use air_hockey::AirHockeyPlugin;
+use air_hockey::components::*;
+use air_hockey::config::GameConfig;
+use air_hockey::resources::*;
+use bevy::input::ButtonState;
+use bevy::input::gamepad::{GamepadConnection, GamepadConnectionEvent, RawGamepadButtonChangedEvent, RawGamepadEvent};
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+
+pub fn app() -> App {
+    app_with(MatchSettings::default(), GameConfig::default())
+}
+
+// Every tick is a whole frame, so a low tick rate means long steps
+pub fn app_with(settings: MatchSettings, config: GameConfig) -> App {
+    let tick = Duration::from_secs_f64(1.0 / settings.tick_rate);
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(AirHockeyPlugin {
+            settings: MatchSettings { seed: Some(1), ..settings },
+            config,
+            headless: true,
+            ..default()
+        })
+        .insert_resource(TimeUpdateStrategy::ManualDuration(tick))
+        .insert_resource(Time::<Fixed>::from_duration(tick));
+    app.update();
+    app
+}
+
+pub fn state(app: &App) -> GameState {
+    app.world().resource::<State<GameState>>().get().clone()
+}
+
+pub fn set_state(app: &mut App, state: GameState) {
+    app.world_mut().resource_mut::<NextState<GameState>>().set(state);
+    app.update();
+}
+
+pub fn ticks(app: &mut App, count: usize) {
+    for _ in 0..count {
+        app.update();
+    }
+}
+
+// Straight into a match, with the serve delay already over
+pub fn start_match(app: &mut App) {
+    set_state(app, GameState::Playing);
+    skip_serve(app);
+}
+
+pub fn skip_serve(app: &mut App) {
+    let mut timer = app.world_mut().resource_mut::<PuckDelayTimer>();
+    let duration = timer.0.duration();
+    timer.0.tick(duration);
+}
+
+pub fn puck(app: &mut App) -> (Vec2, Vec2) {
+    let world = app.world_mut();
+    let (position, velocity) = world.query_filtered::<(&Position, &Velocity), With<Puck>>().single(world).unwrap();
+    (position.0, velocity.0)
+}
+
+pub fn set_puck(app: &mut App, position: Vec2, velocity: Vec2) {
+    let world = app.world_mut();
+    let (mut current, mut previous, mut current_velocity) = world
+        .query_filtered::<(&mut Position, &mut PreviousPosition, &mut Velocity), With<Puck>>()
+        .single_mut(world)
+        .unwrap();
+    current.0 = position;
+    previous.0 = position;
+    current_velocity.0 = velocity;
+}
+
+pub fn paddle(app: &mut App, side: Side) -> Vec2 {
+    let world = app.world_mut();
+    world
+        .query::<(&Position, &Paddle)>()
+        .iter(world)
+        .find(|(_, paddle)| paddle.side == side)
+        .map(|(position, _)| position.0)
+        .unwrap()
+}
+
+pub fn set_paddle(app: &mut App, side: Side, position: Vec2) {
+    let world = app.world_mut();
+    for (mut current, mut previous, paddle) in world.query::<(&mut Position, &mut PreviousPosition, &Paddle)>().iter_mut(world) {
+        if paddle.side == side {
+            current.0 = position;
+            previous.0 = position;
+        }
+    }
+}
+
+pub fn score(app: &App) -> (u32, u32) {
+    let score = app.world().resource::<Score>();
+    (score.left, score.right)
+}
+
+// Key presses go in as window messages, the same way a real keyboard's do
+pub fn press(app: &mut App, key_code: KeyCode) {
+    key(app, key_code, ButtonState::Pressed);
+}
+
+pub fn release(app: &mut App, key_code: KeyCode) {
+    key(app, key_code, ButtonState::Released);
+}
+
+fn key(app: &mut App, key_code: KeyCode, state: ButtonState) {
+    app.world_mut().write_message(KeyboardInput {
+        key_code,
+        logical_key: Key::Unidentified(bevy::input::keyboard::NativeKey::Unidentified),
+        state,
+        text: None,
+        repeat: false,
+        window: Entity::PLACEHOLDER,
+    });
+}
+
+// A controller plugged in, ready for the next update
+pub fn connect_gamepad(app: &mut App) -> Entity {
+    let world = app.world_mut();
+    let gamepad = world.spawn_empty().id();
+    world.write_message(GamepadConnectionEvent::new(
+        gamepad,
+        GamepadConnection::Connected {
+            name: "Test gamepad".to_string(),
+            vendor_id: None,
+            product_id: None,
+        },
+    ));
+    app.update();
+    gamepad
+}
+
+pub fn press_button(app: &mut App, gamepad: Entity, button: GamepadButton) {
+    app.world_mut().write_message(RawGamepadEvent::Button(RawGamepadButtonChangedEvent::new(gamepad, button, 1.0)));
+}
+
+pub fn release_button(app: &mut App, gamepad: Entity, button: GamepadButton) {
+    app.world_mut().write_message(RawGamepadEvent::Button(RawGamepadButtonChangedEvent::new(gamepad, button, 0.0)));
+}
+
+// Presses the only button with the given marker, as clicking it would
+pub fn click<T: Component>(app: &mut App) {
+    let world = app.world_mut();
+    let mut interaction = world.query_filtered::<&mut Interaction, With<T>>().single_mut(world).unwrap();
+    *interaction = Interaction::Pressed;
+    app.update();
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use air_hockey::components::Side;
+use air_hockey::config::GameConfig;
+use air_hockey::resources::*;
+use bevy::prelude::*;
+use common::*;
+
+#[test]
+fn puck_waits_out_the_serve_delay() {
+    let mut app = app();
+    set_state(&mut app, GameState::Playing);
+    let (position, velocity) = puck(&mut app);
+    assert_eq!(position, Vec2::ZERO);
+    assert_eq!(velocity.x.abs(), GameConfig::default().puck_speed);
+
+    // Two seconds at 60 ticks a second
+    ticks(&mut app, 110);
+    assert_eq!(puck(&mut app).0, Vec2::ZERO);
+
+    ticks(&mut app, 20);
+    let (position, _) = puck(&mut app);
+    assert!(position.x.abs() > 0.0 && position.y == 0.0, "puck still at {position}");
+}
+
+#[test]
+fn puck_bounces_off_the_side_walls() {
+    let mut app = app();
+    start_match(&mut app);
+    set_puck(&mut app, Vec2::new(0.0, 200.0), Vec2::new(0.0, 600.0));
+
+    ticks(&mut app, 10);
+    let (position, velocity) = puck(&mut app);
+    assert!(velocity.y < 0.0, "puck still heading up at {velocity}");
+    let config = GameConfig::default();
+    assert!(position.y <= config.rink_height / 2.0 - config.puck_size / 2.0);
+}
+
+#[test]
+fn paddle_deflects_the_puck() {
+    let mut app = app();
+    start_match(&mut app);
+    set_paddle(&mut app, Side::Left, Vec2::new(-300.0, 0.0));
+    set_puck(&mut app, Vec2::new(-150.0, 0.0), Vec2::new(-600.0, 0.0));
+
+    ticks(&mut app, 20);
+    let (position, velocity) = puck(&mut app);
+    assert!(velocity.x > 0.0, "puck not sent back, moving at {velocity}");
+    let config = GameConfig::default();
+    let contact = config.paddle_radius + config.puck_size / 2.0;
+    assert!(position.distance(paddle(&mut app, Side::Left)) >= contact - 0.01);
+}
+
+#[test]
+fn keys_move_the_paddles() {
+    let mut app = app();
+    start_match(&mut app);
+    let start = paddle(&mut app, Side::Left);
+
+    press(&mut app, KeyCode::KeyW);
+    ticks(&mut app, 10);
+    release(&mut app, KeyCode::KeyW);
+    app.update();
+    let moved = paddle(&mut app, Side::Left);
+    assert!(moved.y > start.y && moved.x == start.x, "paddle went from {start} to {moved}");
+
+    ticks(&mut app, 10);
+    assert_eq!(paddle(&mut app, Side::Left), moved);
+}
+
+#[test]
+fn goal_scores_and_serves_again() {
+    let mut app = app();
+    start_match(&mut app);
+    set_paddle(&mut app, Side::Right, Vec2::new(300.0, 200.0));
+    set_puck(&mut app, Vec2::new(300.0, 0.0), Vec2::new(900.0, 0.0));
+
+    ticks(&mut app, 15);
+    assert_eq!(score(&app), (1, 0));
+    let config = GameConfig::default();
+    let (position, velocity) = puck(&mut app);
+    assert_eq!(position, Vec2::ZERO);
+    assert_eq!(velocity, Vec2::new(-config.puck_speed, 0.0)); // Toward the side that scored
+    assert_eq!(paddle(&mut app, Side::Right), config.paddle_start(Side::Right));
+
+    // Held for the serve delay again
+    ticks(&mut app, 60);
+    assert_eq!(puck(&mut app).0, Vec2::ZERO);
+}
+
+#[test]
+fn puck_off_the_end_wall_is_no_goal() {
+    let mut app = app();
+    start_match(&mut app);
+    set_paddle(&mut app, Side::Right, Vec2::new(300.0, -200.0));
+    set_puck(&mut app, Vec2::new(300.0, 200.0), Vec2::new(900.0, 0.0));
+
+    ticks(&mut app, 15);
+    assert_eq!(score(&app), (0, 0));
+    assert!(puck(&mut app).1.x < 0.0);
+}
+
+#[test]
+fn reaching_the_target_score_ends_the_match() {
+    let mut app = app_with(MatchSettings { target_score: 1, ..default() }, GameConfig::default());
+    start_match(&mut app);
+    set_paddle(&mut app, Side::Left, Vec2::new(-300.0, 200.0));
+    set_puck(&mut app, Vec2::new(-300.0, 0.0), Vec2::new(-900.0, 0.0));
+
+    ticks(&mut app, 15);
+    assert_eq!(score(&app), (0, 1));
+    assert_eq!(state(&app), GameState::GameOver);
+}
+
+// The sweep tests in systems.rs cover single ticks. This one has the whole game
+// running slowly, with the paddle driven from the keyboard.
+#[test]
+fn fast_paddle_cannot_pass_through_the_puck() {
+    let config = GameConfig { paddle_speed: 2000.0, ..default() };
+    let settings = MatchSettings { tick_rate: 5.0, physics: PhysicsMode::Realistic, ..default() };
+    let mut app = app_with(settings, config);
+    start_match(&mut app);
+    set_paddle(&mut app, Side::Left, Vec2::new(-320.0, 0.0));
+    set_puck(&mut app, Vec2::new(-200.0, 0.0), Vec2::ZERO);
+
+    press(&mut app, KeyCode::KeyD);
+    app.update();
+    assert!(puck(&mut app).1.x > 0.0, "paddle went through the resting puck");
+}
+
+// Everything a match ends on: puck position and velocity, both paddles, the
+// score and the tick
+type Outcome = (Vec2, Vec2, Vec2, Vec2, (u32, u32), u64);
+
+// Ten seconds against the CPU, with the left player's keys pressed and released
+// on a schedule
+fn scripted_match() -> Outcome {
+    let settings = MatchSettings { cpu_side: Some(Side::Right), physics: PhysicsMode::Realistic, ..default() };
+    let mut app = app_with(settings, GameConfig::default());
+    start_match(&mut app);
+
+    let script = [
+        (20, KeyCode::KeyD, true),
+        (45, KeyCode::KeyW, true),
+        (70, KeyCode::KeyD, false),
+        (90, KeyCode::KeyW, false),
+        (150, KeyCode::KeyS, true),
+        (200, KeyCode::KeyD, true),
+        (260, KeyCode::KeyS, false),
+        (300, KeyCode::KeyD, false),
+        (420, KeyCode::KeyA, true),
+        (480, KeyCode::KeyA, false),
+    ];
+    for tick in 0..600 {
+        for (_, key, pressed) in script.iter().filter(|(at, _, _)| *at == tick) {
+            match pressed {
+                true => press(&mut app, *key),
+                false => release(&mut app, *key),
+            }
+        }
+        app.update();
+    }
+
+    let (position, velocity) = puck(&mut app);
+    let (left, right) = (paddle(&mut app, Side::Left), paddle(&mut app, Side::Right));
+    let tick = app.world().resource::<SimulationTick>().0;
+    (position, velocity, left, right, score(&app), tick)
+}
+
+#[test]
+fn same_seed_and_inputs_play_out_the_same() {
+    let first = scripted_match();
+    let second = scripted_match();
+    assert!(first.5 > 0);
+    assert_eq!(first, second);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use air_hockey::config::GameConfig;
+use air_hockey::replay::*;
+use air_hockey::resources::*;
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use common::*;
+
+fn replay_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("air_hockey_{name}_{}", std::process::id()))
+}
+
+// Twenty seconds of two players trading the puck, each stepping aside in turn
+// to let the other score
+fn record_match() -> (ReplayFile, (u32, u32)) {
+    let mut app = app();
+    set_state(&mut app, GameState::Playing);
+
+    ticks(&mut app, 300);
+    press(&mut app, KeyCode::ArrowDown);
+    ticks(&mut app, 20);
+    release(&mut app, KeyCode::ArrowDown);
+    ticks(&mut app, 280);
+    press(&mut app, KeyCode::KeyW);
+    ticks(&mut app, 600);
+
+    let replay = app.world().resource::<ReplayRecorder>().0.clone();
+    (replay, score(&app))
+}
+
+// Plays a saved replay through start_replay, the way the replay viewer does,
+// for as long as it was recorded
+fn play_back(path: &Path, ticks_to_play: u64) -> App {
+    let mut app = app();
+    let path = path.to_path_buf();
+    let started = app
+        .world_mut()
+        .run_system_once(move |mut commands: Commands, mut settings: ResMut<MatchSettings>, mut config: ResMut<GameConfig>| {
+            start_replay(&mut commands, &path, &mut settings, &mut config)
+        })
+        .unwrap();
+    assert!(started);
+    set_state(&mut app, GameState::Playing);
+    ticks(&mut app, ticks_to_play as usize);
+    app
+}
+
+#[test]
+fn recorded_match_plays_back_the_same() {
+    let (replay, recorded_score) = record_match();
+    assert!(!replay.score_events.is_empty(), "no goals to check the playback against");
+    let dir = replay_dir("playback");
+    let path = replay.save(&dir).unwrap();
+
+    let app = play_back(&path, replay.tick_count());
+    let playback = app.world().resource::<ReplayPlayback>();
+    assert_eq!(playback.desync, None);
+    assert_eq!(playback.next_score_event, replay.score_events.len());
+    assert_eq!(score(&app), recorded_score);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn changed_inputs_are_reported_as_a_desync() {
+    let (mut replay, _) = record_match();
+
+    // The left player stays in front of the goal instead of stepping aside
+    let run = replay.inputs.iter_mut().find(|run| run.left == [0.0, 1.0]).unwrap();
+    run.left = [0.0, 0.0];
+    let dir = replay_dir("desync");
+    let path = replay.save(&dir).unwrap();
+
+    let app = play_back(&path, replay.tick_count());
+    assert!(app.world().resource::<ReplayPlayback>().desync.is_some());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replays_saved_in_the_same_second_are_all_kept() {
+    let dir = replay_dir("replays");
+    let replay = ReplayFile {
+        version: REPLAY_VERSION,
+        seed: 1,
+        settings: MatchSettings::default(),
+        config: GameConfig::default(),
+        inputs: Vec::new(),
+        score_events: Vec::new(),
+        snapshots: Vec::new(),
+    };
+
+    let first = replay.save(&dir).unwrap();
+    let second = replay.save(&dir).unwrap();
+    let third = replay.save(&dir).unwrap();
+    assert_ne!(first, second);
+    assert_ne!(second, third);
+    for path in [&first, &second, &third] {
+        assert!(ReplayFile::load(path).is_ok(), "{} didn't load", path.display());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replay_with_a_bad_tick_rate_is_refused() {
+    let dir = replay_dir("tick_rate");
+    for tick_rate in [0.0, -60.0, f64::NAN, f64::INFINITY] {
+        let replay = ReplayFile {
+            version: REPLAY_VERSION,
+            seed: 1,
+            settings: MatchSettings { tick_rate, ..default() },
+            config: GameConfig::default(),
+            inputs: Vec::new(),
+            score_events: Vec::new(),
+            snapshots: Vec::new(),
+        };
+        let path = replay.save(&dir).unwrap();
+        assert!(ReplayFile::load(&path).is_err(), "tick_rate {tick_rate} was played");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
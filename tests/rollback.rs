@@ -0,0 +1,71 @@
+mod common;
+
+use air_hockey::components::Side;
+use air_hockey::net::NetSession;
+use air_hockey::resources::*;
+use air_hockey::rollback::RollbackSession;
+use bevy::prelude::*;
+use common::*;
+
+// The right player steps out of the puck's way soon after the serve, letting
+// the left score
+fn remote_input(tick: u64) -> Vec2 {
+    match tick {
+        100..120 => Vec2::Y,
+        _ => Vec2::ZERO,
+    }
+}
+
+// A rollback match hosted on the left, with no peer behind it. The peer's
+// inputs are handed to the session directly instead of arriving over the socket.
+fn rollback_app() -> App {
+    let mut app = app();
+    let net = NetSession::bind("127.0.0.1:0", None, Side::Left, &LaunchOptions::default()).unwrap();
+    app.insert_resource(net).insert_resource(RollbackSession::new(1, MatchSettings::default()));
+    set_state(&mut app, GameState::Countdown);
+    set_state(&mut app, GameState::Playing);
+    app
+}
+
+fn tick(app: &App) -> u64 {
+    app.world().resource::<SimulationTick>().0
+}
+
+fn receive(app: &mut App, first_tick: u64, last_tick: u64) {
+    let played = tick(app);
+    let mut session = app.world_mut().resource_mut::<RollbackSession>();
+    session.receive_inputs(first_tick, (first_tick..last_tick).map(remote_input), played);
+}
+
+fn play_until(app: &mut App, until: u64) {
+    while tick(app) < until {
+        app.update();
+    }
+}
+
+type Outcome = (Vec2, Vec2, Vec2, Vec2, (u32, u32));
+
+fn outcome(app: &mut App) -> Outcome {
+    let (position, velocity) = puck(app);
+    (position, velocity, paddle(app, Side::Left), paddle(app, Side::Right), score(app))
+}
+
+#[test]
+fn late_input_is_rolled_back_to() {
+    let mut on_time = rollback_app();
+    receive(&mut on_time, 0, 400);
+    play_until(&mut on_time, 125);
+    let right_on_time = paddle(&mut on_time, Side::Right);
+    play_until(&mut on_time, 400);
+
+    // Everything from tick 100 on turns up 25 ticks late, after the right paddle
+    // has been guessed to stay where it was
+    let mut late = rollback_app();
+    receive(&mut late, 0, 100);
+    play_until(&mut late, 125);
+    assert_ne!(paddle(&mut late, Side::Right), right_on_time);
+    receive(&mut late, 100, 400);
+    play_until(&mut late, 400);
+
+    assert_eq!(outcome(&mut late), outcome(&mut on_time));
+}
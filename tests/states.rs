@@ -0,0 +1,119 @@
+mod common;
+
+use air_hockey::components::*;
+use air_hockey::goal_replay::GoalReplay;
+use air_hockey::resources::*;
+use bevy::prelude::*;
+use common::*;
+
+#[test]
+fn start_button_counts_down_into_a_match() {
+    let mut app = app();
+    assert_eq!(state(&app), GameState::StartScreen);
+
+    click::<TwoPlayerButton>(&mut app);
+    app.update();
+    assert_eq!(state(&app), GameState::Countdown);
+    assert_eq!(app.world().resource::<MatchSettings>().cpu_side, None);
+
+    // Three seconds at 60 frames a second
+    ticks(&mut app, 170);
+    assert_eq!(state(&app), GameState::Countdown);
+    ticks(&mut app, 20);
+    assert_eq!(state(&app), GameState::Playing);
+    assert!(!app.world().contains_resource::<CountdownTimer>());
+    assert_eq!(puck(&mut app).0, Vec2::ZERO);
+}
+
+#[test]
+fn one_player_hands_the_right_paddle_to_the_cpu() {
+    let mut app = app();
+    click::<OnePlayerButton>(&mut app);
+    set_state(&mut app, GameState::Playing);
+
+    let world = app.world_mut();
+    let cpu: Vec<Side> = world
+        .query_filtered::<&Paddle, With<CpuPaddle>>()
+        .iter(world)
+        .map(|paddle| paddle.side)
+        .collect();
+    assert_eq!(cpu, [Side::Right]);
+}
+
+#[test]
+fn escape_pauses_and_resumes() {
+    let mut app = app();
+    start_match(&mut app);
+
+    press(&mut app, KeyCode::Escape);
+    app.update();
+    assert!(app.world().resource::<GamePaused>().0);
+    let frozen = puck(&mut app);
+    ticks(&mut app, 30);
+    assert_eq!(puck(&mut app), frozen);
+
+    release(&mut app, KeyCode::Escape);
+    app.update();
+    press(&mut app, KeyCode::Escape);
+    app.update();
+    assert!(!app.world().resource::<GamePaused>().0);
+    ticks(&mut app, 5);
+    assert_ne!(puck(&mut app).0, frozen.0);
+}
+
+#[test]
+fn restart_starts_a_fresh_match() {
+    let mut app = app();
+    start_match(&mut app);
+    app.world_mut().resource_mut::<Score>().left = 3;
+    set_puck(&mut app, Vec2::new(100.0, 100.0), Vec2::new(300.0, 300.0));
+
+    press(&mut app, KeyCode::Escape);
+    app.update();
+    click::<RestartButton>(&mut app);
+    assert_eq!(score(&app), (0, 0));
+    assert!(!app.world().resource::<GamePaused>().0);
+
+    app.update();
+    assert_eq!(state(&app), GameState::Countdown);
+    let world = app.world_mut();
+    assert_eq!(world.query_filtered::<(), With<Puck>>().iter(world).count(), 0);
+
+    ticks(&mut app, 190);
+    assert_eq!(state(&app), GameState::Playing);
+    assert_eq!(puck(&mut app).0, Vec2::ZERO);
+    assert!(!app.world().resource::<PuckDelayTimer>().0.is_finished());
+}
+
+// Scores on the left goal, which starts the instant replay over the serve delay
+fn score_into_goal_replay(app: &mut App) {
+    start_match(app);
+    set_paddle(app, Side::Right, Vec2::new(300.0, 200.0));
+    set_puck(app, Vec2::new(300.0, 0.0), Vec2::new(900.0, 0.0));
+    ticks(app, 15);
+    assert!(app.world().contains_resource::<GoalReplay>());
+}
+
+#[test]
+fn start_during_a_goal_replay_only_pauses() {
+    let mut app = app();
+    let gamepad = connect_gamepad(&mut app);
+    score_into_goal_replay(&mut app);
+
+    press_button(&mut app, gamepad, GamepadButton::Start);
+    app.update();
+    assert!(app.world().resource::<GamePaused>().0);
+    assert!(app.world().contains_resource::<GoalReplay>());
+}
+
+#[test]
+fn south_skips_a_goal_replay() {
+    let mut app = app();
+    let gamepad = connect_gamepad(&mut app);
+    score_into_goal_replay(&mut app);
+
+    press_button(&mut app, gamepad, GamepadButton::South);
+    app.update();
+    assert!(!app.world().resource::<GamePaused>().0);
+    assert!(!app.world().contains_resource::<GoalReplay>());
+}